@@ -0,0 +1,20 @@
+pub const H_TOTAL: u32 = 1880;
+pub const V_TOTAL: u32 = 1082;
+pub const H_DISPLAY: u32 = 1400;
+pub const V_DISPLAY: u32 = 1050;
+pub const VERTICAL_SYNC: f64 = 60.00;
+pub const DOT_CLOCK: u32 = (H_TOTAL as f64 * V_TOTAL as f64 * VERTICAL_SYNC) as u32;
+
+pub mod modulator;
+pub mod render;
+pub mod tmds;
+
+// Converts the index of a visible pixel between 0 and (H_DISPLAY * V_DISPLAY) into an
+// index between 0 and (H_TOTAL * V_TOTAL).
+pub fn visible_to_total_index(pixel_index: usize) -> u32 {
+    pixel_index as u32
+        // Every time pixel_index exceeds H_DISPLAY add the length of an HBlank interval.
+        + (pixel_index as u32 / H_DISPLAY) * (H_TOTAL - H_DISPLAY)
+        // Same as above but adds the length of a VBlank interval.
+        + (pixel_index as u32 / (H_DISPLAY * V_DISPLAY)) * H_TOTAL * (V_TOTAL - V_DISPLAY)
+}
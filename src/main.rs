@@ -1,24 +1,15 @@
-const H_TOTAL: u32 = 1880;
-const V_TOTAL: u32 = 1082;
-const H_DISPLAY: u32 = 1400;
-const V_DISPLAY: u32 = 1050;
-const VERTICAL_SYNC: f64 = 60.00;
-const DOT_CLOCK: u32 = (H_TOTAL as f64 * V_TOTAL as f64 * VERTICAL_SYNC) as u32;
-const THREADS: u32 = 8;
+use std::sync::Arc;
 
-use std::sync::{mpsc, Arc};
-use std::thread;
-
-use log::error;
+use log::{error, info};
 use pixels::{Pixels, SurfaceTexture};
-use winit::event::{Event, VirtualKeyCode};
+use winit::event::Event;
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Fullscreen, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
 
-mod modulator;
-
-use modulator::*;
+use tempest_crt::modulator::*;
+use tempest_crt::render::*;
+use tempest_crt::{DOT_CLOCK, H_DISPLAY, H_TOTAL, V_DISPLAY, V_TOTAL};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let event_loop = EventLoop::new();
@@ -41,10 +32,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     //let mut wave_freq = 0;
     let pcm_loader: PcmLoader<Signed16Le> = PcmLoader::open("/tmp/virtualdevice", 44100).unwrap();
-    //pcm_loader.set_interp(Interpolation::Linear);
+    //pcm_loader.set_interp(Interpolation::Linear).unwrap();
     let integrated_loader = PreintegratedLoader::new(pcm_loader);
     let mut carrier = Sine::from_freq(44000000, DOT_CLOCK);
     let mut information = integrated_loader;
+    // The audio can be put through a chain of combinators on its way to the carrier, e.g.
+    // TEMPEST_CHAIN="mix(audio: 0.9, pilot: 0.1)" for a quiet pilot tone under it.
+    let mut chain = std::env::var("TEMPEST_CHAIN")
+        .ok()
+        .map(|chain| chain.parse::<Chain>())
+        .transpose()?;
+    let mut pilot = Sine::from_freq(19000, DOT_CLOCK);
+    let mut integrator = SignalIntegrator::from_dot_clock(DOT_CLOCK);
     //let mut information = pcm_loader;
     //let mut information = Sine::from_freq(wave_freq, DOT_CLOCK);
     /*let mut modulator = AmplitudeModulator {
@@ -53,15 +52,29 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     };*/
     let mut modulator = FrequencyModulator {
         carrier: Arc::from(carrier),
-        information: Arc::from(information.samples()),
+        information: fm_information(&mut information, chain.as_mut(), pilot, &mut integrator)?,
     };
     let mut total_index_offset = 0;
+    let mut render_pool = RenderPool::sized_to_machine();
+    //render_pool.set_predistortion(Predistortion::Srgb);
+    //render_pool.set_quantizer(Quantizer::NotchedErrorFeedback { frequency: 44000000 });
+    // Over HDMI or DVI the bytes are TMDS encoded, so they are better picked by their symbols:
+    //render_pool.set_quantizer(Quantizer::Tmds(Arc::new(tempest_crt::tmds::TmdsQuantizer::new(44000000))));
 
     event_loop.run(move |event, _, control_flow| {
         if let Event::RedrawRequested(_) = event {
             let frame = pixels.frame_mut();
-            //draw_frame(&modulator, total_index_offset, frame);
-            draw_frame_threaded(Arc::new(modulator.clone()), frame);
+            render_pool.render(Arc::new(modulator.clone()), frame);
+
+            let timing = render_pool.timing();
+            if timing.frames.is_multiple_of(600) {
+                info!(
+                    "{} threads: {:?} average, {:?} worst frame time",
+                    render_pool.threads(),
+                    timing.average,
+                    timing.worst
+                );
+            }
 
             if pixels
                 .render()
@@ -73,10 +86,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
 
             carrier.next_frame(H_TOTAL * V_TOTAL);
+            pilot.next_frame(H_TOTAL * V_TOTAL);
             information.next_frame().unwrap();
             modulator = FrequencyModulator {
                 carrier: Arc::from(carrier),
-                information: Arc::from(information.samples()),
+                information: fm_information(
+                    &mut information,
+                    chain.as_mut(),
+                    pilot,
+                    &mut integrator,
+                )
+                .unwrap(),
             };
 
             // If the next frame's offset would be more than DOT_CLOCK, then we've been drawing
@@ -117,74 +137,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 }
 
-#[allow(dead_code)]
-fn draw_frame(modulator: &dyn Signal, frame: &mut [u8]) {
-    for pixel in frame.chunks_exact_mut(4).enumerate() {
-        let total_index = visible_to_total_index(pixel.0);
-
-        let grayscale = (modulator.sample(total_index) * (255.0 / 2.0) + 255.0 / 2.0).round() as u8;
-        pixel.1[0] = grayscale;
-        pixel.1[1] = grayscale;
-        pixel.1[2] = grayscale;
-        pixel.1[3] = 255;
-    }
-}
-
-fn draw_frame_threaded(modulator: Arc<dyn Signal>, frame: &mut [u8]) {
-    let (tx, rx) = mpsc::channel();
-
-    let pixels_per_thread = H_DISPLAY * V_DISPLAY / THREADS;
-    for i in 0..THREADS {
-        let tx = tx.clone();
-        let modulator = modulator.clone();
-        let chunk = (i * pixels_per_thread)..(i * pixels_per_thread) + pixels_per_thread;
-
-        thread::spawn(move || {
-            let grayscale_chunk = chunk
-                .map(|pixel_index| {
-                    let total_index = visible_to_total_index(pixel_index as usize);
-
-                    (modulator.sample(total_index) * (255.0 / 2.0) + 255.0 / 2.0).round() as u8
-                })
-                .collect::<Vec<_>>();
-
-            tx.send((i, grayscale_chunk)).unwrap();
-        });
-    }
-    // If the chunks couldn't be divided evenly, then assign the remaining work to another thread.
-    if H_DISPLAY * V_DISPLAY % THREADS != 0 {
-        let grayscale_chunk = ((pixels_per_thread * THREADS)..(H_DISPLAY * V_DISPLAY))
-            .map(|pixel_index| {
-                let total_index = visible_to_total_index(pixel_index as usize);
-
-                (modulator.sample(total_index) * (255.0 / 2.0) + 255.0 / 2.0).round() as u8
-            })
-            .collect::<Vec<_>>();
-
-        tx.send((THREADS, grayscale_chunk)).unwrap();
-    }
-    drop(tx);
-
-    let mut grayscale_chunks: Vec<(u32, Vec<u8>)> = rx.iter().collect();
-    grayscale_chunks.sort_by_key(|thread| thread.0);
-    let frame_vec = grayscale_chunks
-        .iter()
-        .fold(Vec::new(), |acc, chunk| [&acc[..], &chunk.1[..]].concat());
-
-    for pixel in frame.chunks_exact_mut(4).zip(frame_vec) {
-        pixel.0[0] = pixel.1;
-        pixel.0[1] = pixel.1;
-        pixel.0[2] = pixel.1;
-        pixel.0[3] = 255;
-    }
-}
-
-// Converts the index of a visible pixel between 0 and (H_DISPLAY * V_DISPLAY) into an
-// index between 0 and (H_TOTAL * V_TOTAL).
-fn visible_to_total_index(pixel_index: usize) -> u32 {
-    pixel_index as u32
-        // Every time pixel_index exceeds H_DISPLAY add the length of an HBlank interval.
-        + (pixel_index as u32 / H_DISPLAY) * (H_TOTAL - H_DISPLAY)
-        // Same as above but adds the length of a VBlank interval.
-        + (pixel_index as u32 / (H_DISPLAY * V_DISPLAY)) * H_TOTAL * (V_TOTAL - V_DISPLAY)
+// The integrated audio for the current frame, either straight from the loader or put through
+// the chain and integrated from there.
+fn fm_information(
+    information: &mut PreintegratedLoader<Signed16Le>,
+    chain: Option<&mut Chain>,
+    pilot: Sine,
+    integrator: &mut SignalIntegrator,
+) -> Result<Arc<dyn IntSignal>, Box<dyn std::error::Error>> {
+    let Some(chain) = chain else {
+        return Ok(Arc::from(information.samples()));
+    };
+    let inputs: [(&str, Arc<dyn Signal>); 2] = [
+        ("audio", Arc::from(information.loader().samples())),
+        ("pilot", Arc::new(pilot)),
+    ];
+    let signal = chain.build(&inputs)?;
+    Ok(Arc::from(integrator.integrate(&signal, H_TOTAL * V_TOTAL)))
 }
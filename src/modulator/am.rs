@@ -1,4 +1,4 @@
-use super::Signal;
+use super::{Signal, CHUNK};
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -14,4 +14,17 @@ impl Signal for AmplitudeModulator {
 
         information_amplitude * carrier_amplitude
     }
+
+    fn fill(&self, start: u32, samples: &mut [f32]) {
+        let mut information = [0.0; CHUNK];
+        for (start, samples) in (start..).step_by(CHUNK).zip(samples.chunks_mut(CHUNK)) {
+            let information = &mut information[..samples.len()];
+            self.information.fill(start, information);
+            self.carrier.fill(start, samples);
+
+            for (sample, information) in samples.iter_mut().zip(information) {
+                *sample *= (*information + 1.0) / 2.0;
+            }
+        }
+    }
 }
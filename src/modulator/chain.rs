@@ -0,0 +1,321 @@
+use super::{Clip, Clipping, Delay, Gain, Mix, Multiply, Offset, Signal, Silence, Sum};
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// The combinators, written out as text so that a chain can come from config rather than
+// code. For example:
+//
+//     mix(audio: 0.9, pilot: 0.1)
+//     clip(gain(audio, 4), 0.8, soft)
+//     multiply(audio, offset(gain(lfo, 0.5), 0.5))
+//     sum(audio, delay(gain(audio, 0.3), 1000))
+//
+// Names stand for the signals handed to build every frame, and numbers on their own are
+// constant signals. Clipping is hard unless `soft` is given.
+pub struct Chain {
+    root: Node,
+}
+
+enum Node {
+    Input(String),
+    Constant(f32),
+    Sum(Vec<Node>),
+    Mix(Vec<(Node, f32)>),
+    Gain(Box<Node>, f32),
+    Offset(Box<Node>, f32),
+    Multiply(Box<Node>, Box<Node>),
+    // Holds on to the delayed signal from the frame before.
+    Delay(Box<Node>, u32, Option<Arc<dyn Signal>>),
+    Clip(Box<Node>, f32, Clipping),
+}
+
+impl Chain {
+    // Puts the chain together for the current frame out of the named inputs.
+    // Call once per frame, since delays keep each frame's signal for the next.
+    pub fn build(
+        &mut self,
+        inputs: &[(&str, Arc<dyn Signal>)],
+    ) -> Result<Arc<dyn Signal>, Box<dyn Error>> {
+        build(&mut self.root, inputs)
+    }
+}
+
+fn build(
+    node: &mut Node,
+    inputs: &[(&str, Arc<dyn Signal>)],
+) -> Result<Arc<dyn Signal>, Box<dyn Error>> {
+    Ok(match node {
+        Node::Input(name) => inputs
+            .iter()
+            .find(|(input, _)| input == name)
+            .map(|(_, signal)| signal.clone())
+            .ok_or_else(|| format!("no input called {name}"))?,
+        Node::Constant(value) => Arc::new(Offset {
+            signal: Silence,
+            offset: *value,
+        }),
+        Node::Sum(nodes) => Arc::new(Sum {
+            signals: nodes
+                .iter_mut()
+                .map(|node| build(node, inputs))
+                .collect::<Result<_, _>>()?,
+        }),
+        Node::Mix(nodes) => Arc::new(Mix {
+            inputs: nodes
+                .iter_mut()
+                .map(|(node, level)| Ok((build(node, inputs)?, *level)))
+                .collect::<Result<_, Box<dyn Error>>>()?,
+        }),
+        Node::Gain(node, gain) => Arc::new(Gain {
+            signal: build(node, inputs)?,
+            gain: *gain,
+        }),
+        Node::Offset(node, offset) => Arc::new(Offset {
+            signal: build(node, inputs)?,
+            offset: *offset,
+        }),
+        Node::Multiply(a, b) => Arc::new(Multiply {
+            a: build(a, inputs)?,
+            b: build(b, inputs)?,
+        }),
+        Node::Delay(node, pixels, previous) => {
+            let mut delay = Delay::new(build(node, inputs)?, *pixels);
+            delay.previous = previous.replace(delay.signal.clone());
+            Arc::new(delay)
+        }
+        Node::Clip(node, limit, clipping) => Arc::new(Clip {
+            signal: build(node, inputs)?,
+            limit: *limit,
+            clipping: *clipping,
+        }),
+    })
+}
+
+impl FromStr for Chain {
+    type Err = Box<dyn Error>;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { text, position: 0 };
+        let root = parser.node()?;
+        parser.skip_space();
+        if parser.position < text.len() {
+            return Err(parser.error("expected the end of the chain"));
+        }
+        Ok(Self { root })
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> Box<dyn Error> {
+        format!("{message} at {:?}", &self.text[self.position..]).into()
+    }
+
+    fn skip_space(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    // Takes `c` if it's next.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_space();
+        let found = self.text[self.position..].starts_with(c);
+        if found {
+            self.position += c.len_utf8();
+        }
+        found
+    }
+
+    // A name or a number.
+    fn word(&mut self) -> Result<&str, Box<dyn Error>> {
+        self.skip_space();
+        let rest = &self.text[self.position..];
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || "_.+-".contains(c)))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name or a number"));
+        }
+        self.position += len;
+        Ok(&rest[..len])
+    }
+
+    fn node(&mut self) -> Result<Node, Box<dyn Error>> {
+        let word = self.word()?;
+        if let Ok(value) = word.parse() {
+            return Ok(Node::Constant(value));
+        }
+        let word = word.to_string();
+        if !self.eat('(') {
+            return Ok(Node::Input(word));
+        }
+
+        // Every argument is a node, with a level after a colon for mix.
+        let mut args = Vec::new();
+        if !self.eat(')') {
+            loop {
+                let node = self.node()?;
+                let level = match self.eat(':') {
+                    true => Some(self.number()?),
+                    false => None,
+                };
+                args.push((node, level));
+                if self.eat(')') {
+                    break;
+                }
+                if !self.eat(',') {
+                    return Err(self.error("expected , or )"));
+                }
+            }
+        }
+        call(&word, args).map_err(|e| format!("{word}: {e}").into())
+    }
+
+    fn number(&mut self) -> Result<f32, Box<dyn Error>> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| format!("expected a number, not {word:?}").into())
+    }
+}
+
+fn call(name: &str, args: Vec<(Node, Option<f32>)>) -> Result<Node, Box<dyn Error>> {
+    if name != "mix" && args.iter().any(|(_, level)| level.is_some()) {
+        return Err("only mix takes levels".into());
+    }
+    let (mut nodes, levels): (Vec<Node>, Vec<Option<f32>>) = args.into_iter().unzip();
+
+    Ok(match (name, &mut nodes[..]) {
+        ("sum", _) => Node::Sum(nodes),
+        ("mix", _) => Node::Mix(
+            nodes
+                .into_iter()
+                .zip(levels)
+                .map(|(node, level)| (node, level.unwrap_or(1.0)))
+                .collect(),
+        ),
+        ("gain", [signal, gain]) => Node::Gain(take(signal), number(gain)?),
+        ("offset", [signal, offset]) => Node::Offset(take(signal), number(offset)?),
+        ("multiply", [a, b]) => Node::Multiply(take(a), take(b)),
+        ("delay", [signal, pixels]) => {
+            let pixels = number(pixels)?;
+            if pixels < 0.0 || pixels.fract() != 0.0 {
+                return Err("the delay has to be a whole number of pixels".into());
+            }
+            Node::Delay(take(signal), pixels as u32, None)
+        }
+        ("clip", [signal, limit]) => Node::Clip(take(signal), number(limit)?, Clipping::Hard),
+        ("clip", [signal, limit, Node::Input(clipping)]) => {
+            let clipping = match clipping.as_str() {
+                "hard" => Clipping::Hard,
+                "soft" => Clipping::Soft,
+                _ => return Err(format!("unknown clipping {clipping:?}").into()),
+            };
+            Node::Clip(take(signal), number(limit)?, clipping)
+        }
+        ("gain" | "offset" | "multiply" | "delay" | "clip", _) => {
+            return Err("wrong number of arguments".into())
+        }
+        _ => return Err("unknown combinator".into()),
+    })
+}
+
+fn take(node: &mut Node) -> Box<Node> {
+    Box::new(std::mem::replace(node, Node::Constant(0.0)))
+}
+
+fn number(node: &Node) -> Result<f32, Box<dyn Error>> {
+    match node {
+        Node::Constant(value) => Ok(*value),
+        _ => Err("expected a number".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::Sine;
+    use crate::{DOT_CLOCK, H_TOTAL, V_TOTAL};
+
+    // A constant signal, to tell inputs apart by.
+    fn level(value: f32) -> Arc<dyn Signal> {
+        Arc::new(Offset {
+            signal: Silence,
+            offset: value,
+        })
+    }
+
+    fn evaluate(text: &str, total_index: u32) -> f32 {
+        let mut chain: Chain = text.parse().unwrap();
+        let inputs = [("audio", level(0.5)), ("pilot", level(-0.25))];
+        chain.build(&inputs).unwrap().sample(total_index)
+    }
+
+    #[test]
+    fn builds_every_combinator() {
+        assert_eq!(evaluate("audio", 0), 0.5);
+        assert_eq!(evaluate("0.125", 0), 0.125);
+        assert_eq!(evaluate("sum(audio, pilot, 1)", 0), 1.25);
+        assert_eq!(evaluate("mix(audio: 0.5, pilot: 2)", 0), -0.25);
+        assert_eq!(evaluate("mix(audio, pilot: 2)", 0), 0.0);
+        assert_eq!(evaluate("gain(audio, -3)", 0), -1.5);
+        assert_eq!(evaluate("offset(pilot, 1)", 0), 0.75);
+        assert_eq!(evaluate("multiply(audio, pilot)", 0), -0.125);
+        assert_eq!(evaluate("clip(gain(audio, 4), 0.8)", 0), 0.8);
+        assert_eq!(
+            evaluate("clip(gain(audio, 4), 0.8, soft)", 0),
+            0.8 * 2.5f32.tanh()
+        );
+        assert_eq!(evaluate(" mix ( audio : 1 ,\tpilot:1 ) ", 0), 0.25);
+    }
+
+    #[test]
+    fn delay_carries_on_between_builds() {
+        let frame_size = H_TOTAL * V_TOTAL;
+        let mut chain: Chain = "delay(tone, 100)".parse().unwrap();
+        let mut tone = Sine::from_freq(1000, DOT_CLOCK);
+
+        let first = chain.build(&[("tone", Arc::new(tone))]).unwrap();
+        tone.next_frame(frame_size);
+        let second = chain.build(&[("tone", Arc::new(tone))]).unwrap();
+
+        // The start of the second frame is the end of the first.
+        let end_of_first = Signal::sample(&Sine::from_freq(1000, DOT_CLOCK), frame_size - 100);
+        assert_eq!(second.sample(0), end_of_first);
+        assert_eq!(second.sample(100), Signal::sample(&tone, 0));
+        assert_eq!(
+            first.sample(100),
+            Signal::sample(&Sine::from_freq(1000, DOT_CLOCK), 0)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_chains() {
+        for text in [
+            "",
+            "mix(audio: 1",
+            "mix(audio: loud)",
+            "gain(audio)",
+            "gain(audio, pilot)",
+            "gain(audio: 2, 3)",
+            "delay(audio, 1.5)",
+            "clip(audio, 1, squishy)",
+            "reverb(audio)",
+            "audio pilot",
+            "sum(audio,)",
+        ] {
+            assert!(text.parse::<Chain>().is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn missing_inputs_are_reported_when_built() {
+        let mut chain: Chain = "sum(audio, voice)".parse().unwrap();
+        let error = chain.build(&[("audio", level(0.5))]).err().unwrap();
+        assert_eq!(error.to_string(), "no input called voice");
+    }
+}
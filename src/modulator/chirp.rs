@@ -0,0 +1,118 @@
+use super::Signal;
+use std::error::Error;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Sweep {
+    // The frequency changes by the same number of hertz every second.
+    Linear,
+    // The frequency changes by the same number of octaves every second.
+    Logarithmic,
+}
+
+// A sine whose frequency sweeps from `start` to `end` over `duration` seconds, then starts
+// the sweep over. Phase stays continuous across restarts and frames.
+#[derive(Copy, Clone)]
+pub struct Chirp {
+    start: f64,
+    end: f64,
+    duration: f64,
+    sweep: Sweep,
+    dot_clock: f64,
+    // Seconds into the current sweep at the start of the frame.
+    time_offset: f64,
+    // Phase in turns at the start of the current sweep.
+    phase_offset: f64,
+}
+
+impl Chirp {
+    // The duration has to be above 0, and a logarithmic sweep can't start or end at 0 Hz.
+    pub fn new(
+        start: f64,
+        end: f64,
+        duration: f64,
+        sweep: Sweep,
+        dot_clock: u32,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !(duration > 0.0 && duration.is_finite()) {
+            return Err(format!("a chirp can't last {duration} seconds").into());
+        }
+        if !(start.is_finite() && end.is_finite() && start >= 0.0 && end >= 0.0) {
+            return Err(format!("a chirp can't sweep from {start} Hz to {end} Hz").into());
+        }
+        if let Sweep::Logarithmic = sweep {
+            if start == 0.0 || end == 0.0 {
+                return Err("a logarithmic chirp can't start or end at 0 Hz".into());
+            }
+        }
+
+        Ok(Self {
+            start,
+            end,
+            duration,
+            sweep,
+            dot_clock: dot_clock as f64,
+            time_offset: 0.0,
+            phase_offset: 0.0,
+        })
+    }
+
+    pub fn next_frame(&mut self, frame_size: u32) {
+        self.time_offset += frame_size as f64 / self.dot_clock;
+        while self.time_offset >= self.duration {
+            self.time_offset -= self.duration;
+            self.phase_offset = (self.phase_offset + self.sweep_phase(self.duration)).fract();
+        }
+    }
+
+    // Phase in turns reached `time` seconds into a sweep.
+    fn sweep_phase(&self, time: f64) -> f64 {
+        match self.sweep {
+            Sweep::Linear => {
+                self.start * time + (self.end - self.start) * time * time / (2.0 * self.duration)
+            }
+            // Sweeping nowhere is a constant tone, which the formula below would divide by 0 for.
+            Sweep::Logarithmic if self.start == self.end => self.start * time,
+            Sweep::Logarithmic => {
+                let ratio = self.end / self.start;
+                self.start * self.duration / ratio.ln() * (ratio.powf(time / self.duration) - 1.0)
+            }
+        }
+    }
+}
+
+impl Signal for Chirp {
+    fn sample(&self, total_index: u32) -> f32 {
+        let mut time = self.time_offset + total_index as f64 / self.dot_clock;
+        let mut phase = self.phase_offset;
+        // The sweep may restart partway through the frame.
+        while time >= self.duration {
+            time -= self.duration;
+            phase += self.sweep_phase(self.duration);
+        }
+        phase += self.sweep_phase(time);
+
+        (std::f64::consts::TAU * phase.fract()).sin() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_sweeps_that_never_end_or_start_from_nothing() {
+        assert!(Chirp::new(100.0, 1000.0, 0.0, Sweep::Linear, 1000000).is_err());
+        assert!(Chirp::new(100.0, 1000.0, -1.0, Sweep::Linear, 1000000).is_err());
+        assert!(Chirp::new(0.0, 1000.0, 1.0, Sweep::Linear, 1000000).is_ok());
+        assert!(Chirp::new(0.0, 1000.0, 1.0, Sweep::Logarithmic, 1000000).is_err());
+    }
+
+    #[test]
+    fn logarithmic_sweep_between_equal_frequencies_is_a_tone() {
+        let chirp = Chirp::new(1000.0, 1000.0, 1.0, Sweep::Logarithmic, 1000000).unwrap();
+        for total_index in (0..1000000).step_by(1237) {
+            let expected = (std::f64::consts::TAU * total_index as f64 / 1000.0).sin();
+            assert!((chirp.sample(total_index) as f64 - expected).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use super::fm::FmCarrier;
+use super::phase::Phase;
+use super::{IntSignal, Signal, CHUNK};
+use crate::{H_TOTAL, V_TOTAL};
+use std::num::Wrapping;
+
+// Wrappers for building one signal out of others, e.g. a voice with a quiet pilot tone:
+//
+//     Mix { inputs: vec![(voice, 0.9), (pilot, 0.1)] }
+//
+// Anything that is a Signal works as an input, including Arc<dyn Signal> and Box<dyn Signal>.
+// Sum and Delay are also IntSignals when their inputs are, so they can be used for FM.
+// Scaling an integral would scale the whole turns it has wrapped around by as well, so levels
+// for FM are set before integrating, e.g. with a Mix fed to a SignalIntegrator.
+
+#[derive(Clone)]
+pub struct Sum<S> {
+    pub signals: Vec<S>,
+}
+
+impl<S: Signal> Signal for Sum<S> {
+    fn sample(&self, total_index: u32) -> f32 {
+        self.signals
+            .iter()
+            .map(|signal| signal.sample(total_index))
+            .sum()
+    }
+}
+
+impl<S: IntSignal> IntSignal for Sum<S> {
+    fn sample(&self, total_index: u32) -> Phase {
+        self.signals.iter().fold(Phase(Wrapping(0)), |sum, signal| {
+            sum + signal.sample(total_index)
+        })
+    }
+}
+
+// A sum where every input has its own level.
+#[derive(Clone)]
+pub struct Mix<S> {
+    pub inputs: Vec<(S, f32)>,
+}
+
+impl<S: Signal> Signal for Mix<S> {
+    fn sample(&self, total_index: u32) -> f32 {
+        self.inputs
+            .iter()
+            .map(|(signal, level)| signal.sample(total_index) * level)
+            .sum()
+    }
+}
+
+#[derive(Clone)]
+pub struct Gain<S> {
+    pub signal: S,
+    pub gain: f32,
+}
+
+impl<S: Signal> Signal for Gain<S> {
+    fn sample(&self, total_index: u32) -> f32 {
+        self.signal.sample(total_index) * self.gain
+    }
+}
+
+// Adds a DC offset. The integral of a constant keeps growing, so this is a Signal only.
+#[derive(Clone)]
+pub struct Offset<S> {
+    pub signal: S,
+    pub offset: f32,
+}
+
+impl<S: Signal> Signal for Offset<S> {
+    fn sample(&self, total_index: u32) -> f32 {
+        self.signal.sample(total_index) + self.offset
+    }
+}
+
+// Ring modulation: the product of two signals, with no carrier left over like with AM.
+#[derive(Clone)]
+pub struct Multiply<A, B> {
+    pub a: A,
+    pub b: B,
+}
+
+impl<A: Signal, B: Signal> Signal for Multiply<A, B> {
+    fn sample(&self, total_index: u32) -> f32 {
+        self.a.sample(total_index) * self.b.sample(total_index)
+    }
+}
+
+// Delays a signal by a number of pixels, up to a whole frame. Signals only cover the current
+// frame, so the first `pixels` pixels of a frame come from the signal as it was for the frame
+// before, which next_frame keeps around.
+#[derive(Clone)]
+pub struct Delay<S> {
+    pub signal: S,
+    // Without one, the first `pixels` pixels hold the signal's first value.
+    pub previous: Option<S>,
+    pub pixels: u32,
+}
+
+const FRAME_SIZE: u32 = H_TOTAL * V_TOTAL;
+
+impl<S> Delay<S> {
+    pub fn new(signal: S, pixels: u32) -> Self {
+        Self {
+            signal,
+            previous: None,
+            pixels: pixels.min(FRAME_SIZE),
+        }
+    }
+
+    // Moves on to the next frame, where the signal is `signal`.
+    pub fn next_frame(&mut self, signal: S) {
+        self.previous = Some(std::mem::replace(&mut self.signal, signal));
+    }
+
+    // The signal, or the previous frame's, and the index into it that `total_index` comes from.
+    fn source(&self, total_index: u32) -> (&S, u32) {
+        let pixels = self.pixels.min(FRAME_SIZE);
+        if total_index >= pixels {
+            return (&self.signal, total_index - pixels);
+        }
+        match &self.previous {
+            Some(previous) => (previous, total_index + FRAME_SIZE - pixels),
+            None => (&self.signal, 0),
+        }
+    }
+}
+
+impl<S: Signal> Signal for Delay<S> {
+    fn sample(&self, total_index: u32) -> f32 {
+        let (signal, total_index) = self.source(total_index);
+        signal.sample(total_index)
+    }
+}
+
+impl<S: IntSignal> IntSignal for Delay<S> {
+    fn sample(&self, total_index: u32) -> Phase {
+        let (signal, total_index) = self.source(total_index);
+        signal.sample(total_index)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Clipping {
+    // Cuts off anything past the limit.
+    Hard,
+    // Rounds off into the limit with tanh, which adds fewer harmonics.
+    Soft,
+}
+
+#[derive(Clone)]
+pub struct Clip<S> {
+    pub signal: S,
+    pub limit: f32,
+    pub clipping: Clipping,
+}
+
+impl<S: Signal> Signal for Clip<S> {
+    fn sample(&self, total_index: u32) -> f32 {
+        let sample = self.signal.sample(total_index);
+        match self.clipping {
+            Clipping::Hard => sample.clamp(-self.limit, self.limit),
+            Clipping::Soft => self.limit * (sample / self.limit).tanh(),
+        }
+    }
+}
+
+// Shifts a carrier's phase by a fixed number of turns, e.g. to send the same carrier on
+// several colour channels with different phases. It's still a carrier, so it can be frequency
+// modulated.
+#[derive(Clone)]
+pub struct PhaseShift<C> {
+    pub carrier: C,
+    shift: Phase,
+}
+
+impl<C: FmCarrier> PhaseShift<C> {
+    pub fn new(carrier: C, turns: f32) -> Self {
+        Self {
+            carrier,
+            shift: Phase::from(turns),
+        }
+    }
+}
+
+impl<C: FmCarrier> Signal for PhaseShift<C> {
+    fn sample(&self, total_index: u32) -> f32 {
+        self.carrier.sample_with_deviation(total_index, self.shift)
+    }
+
+    fn fill(&self, start: u32, samples: &mut [f32]) {
+        let deviations = [self.shift; CHUNK];
+        for (start, samples) in (start..).step_by(CHUNK).zip(samples.chunks_mut(CHUNK)) {
+            self.carrier
+                .fill_with_deviation(start, &deviations[..samples.len()], samples)
+        }
+    }
+}
+
+impl<C: FmCarrier> FmCarrier for PhaseShift<C> {
+    fn sample_with_deviation(&self, total_index: u32, deviation: Phase) -> f32 {
+        self.carrier
+            .sample_with_deviation(total_index, deviation + self.shift)
+    }
+
+    fn fill_with_deviation(&self, start: u32, deviations: &[Phase], samples: &mut [f32]) {
+        let mut shifted = [self.shift; CHUNK];
+        let chunks = deviations.chunks(CHUNK).zip(samples.chunks_mut(CHUNK));
+        for (start, (deviations, samples)) in (start..).step_by(CHUNK).zip(chunks) {
+            let shifted = &mut shifted[..samples.len()];
+            for (shifted, &deviation) in shifted.iter_mut().zip(deviations) {
+                *shifted = deviation + self.shift;
+            }
+            self.carrier.fill_with_deviation(start, shifted, samples)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::Silence;
+
+    // Counts pixels from the start of the first frame.
+    struct Ramp {
+        frame: u32,
+    }
+
+    impl Signal for Ramp {
+        fn sample(&self, total_index: u32) -> f32 {
+            (self.frame * FRAME_SIZE + total_index) as f32
+        }
+    }
+
+    // A constant integral.
+    struct Turns(f32);
+
+    impl IntSignal for Turns {
+        fn sample(&self, _total_index: u32) -> Phase {
+            Phase::from(self.0)
+        }
+    }
+
+    // A constant signal, for checking arithmetic on.
+    fn level(value: f32) -> Offset<Silence> {
+        Offset {
+            signal: Silence,
+            offset: value,
+        }
+    }
+
+    #[test]
+    fn sum_adds_signals_and_integrals() {
+        let sum = Sum {
+            signals: vec![level(0.5), level(-0.125), level(0.25)],
+        };
+        assert_eq!(Signal::sample(&sum, 0), 0.625);
+
+        // Integrals add up modulo a whole turn.
+        let turns = Sum {
+            signals: vec![Turns(0.75), Turns(0.5)],
+        };
+        assert_eq!(IntSignal::sample(&turns, 0).0, Phase::from(0.25).0);
+    }
+
+    #[test]
+    fn mix_scales_each_input() {
+        let mix = Mix {
+            inputs: vec![(level(0.5), 0.9), (level(1.0), 0.1)],
+        };
+        assert!((mix.sample(0) - 0.55).abs() < 1e-6);
+    }
+
+    #[test]
+    fn gain_offset_and_multiply() {
+        let gain = Gain {
+            signal: level(0.5),
+            gain: -0.5,
+        };
+        assert_eq!(gain.sample(0), -0.25);
+        let offset = Offset {
+            signal: gain.clone(),
+            offset: 1.0,
+        };
+        assert_eq!(offset.sample(0), 0.75);
+        let product = Multiply {
+            a: gain,
+            b: Ramp { frame: 0 },
+        };
+        assert_eq!(product.sample(8), -2.0);
+    }
+
+    #[test]
+    fn clip_limits_hard_and_soft() {
+        let clip = |value, clipping| {
+            Clip {
+                signal: level(value),
+                limit: 0.5,
+                clipping,
+            }
+            .sample(0)
+        };
+        assert_eq!(clip(0.75, Clipping::Hard), 0.5);
+        assert_eq!(clip(-0.75, Clipping::Hard), -0.5);
+        assert_eq!(clip(0.25, Clipping::Hard), 0.25);
+
+        // Soft clipping bends well inside the limit and never quite reaches it.
+        assert!((clip(0.01, Clipping::Soft) - 0.01).abs() < 1e-5);
+        assert!(clip(0.25, Clipping::Soft) < 0.25);
+        assert!(clip(100.0, Clipping::Soft) <= 0.5);
+        assert!(clip(100.0, Clipping::Soft) > 0.499);
+        assert_eq!(clip(-0.3, Clipping::Soft), -clip(0.3, Clipping::Soft));
+    }
+
+    #[test]
+    fn delay_carries_on_from_the_previous_frame() {
+        let mut delay = Delay::new(Ramp { frame: 0 }, 100);
+        assert_eq!(delay.sample(0), 0.0);
+        assert_eq!(delay.sample(150), 50.0);
+
+        delay.next_frame(Ramp { frame: 1 });
+        for total_index in [0, 99, 100, 1000] {
+            let expected = (FRAME_SIZE + total_index - 100) as f32;
+            assert_eq!(delay.sample(total_index), expected);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use super::Processor;
+use std::f64::consts::TAU;
+
+// Second order IIR section, designed from the RBJ Audio EQ Cookbook formulas.
+// Q sets the sharpness: 0.707 is a flat Butterworth response for the low- and high-pass.
+pub struct Biquad {
+    // Feedforward coefficients, and feedback coefficients with a0 divided out.
+    b: [f64; 3],
+    a: [f64; 2],
+    // Transposed direct form II state.
+    state: [f64; 2],
+}
+
+impl Biquad {
+    // Coefficients as in H(z) = (b0 + b1 z^-1 + b2 z^-2) / (a0 + a1 z^-1 + a2 z^-2).
+    pub(in crate::modulator) fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+            state: [0.0; 2],
+        }
+    }
+
+    // cos(ω) and α for a frequency and Q.
+    fn parameters(frequency: f32, q: f32, sample_rate: usize) -> (f64, f64) {
+        let omega = TAU * frequency as f64 / sample_rate as f64;
+        (omega.cos(), omega.sin() / (2.0 * q as f64))
+    }
+
+    pub fn low_pass(cutoff: f32, q: f32, sample_rate: usize) -> Self {
+        let (cos, alpha) = Self::parameters(cutoff, q, sample_rate);
+        Self::new(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    pub fn high_pass(cutoff: f32, q: f32, sample_rate: usize) -> Self {
+        let (cos, alpha) = Self::parameters(cutoff, q, sample_rate);
+        Self::new(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    // Unity gain at the center, with a bandwidth of center / Q.
+    pub fn band_pass(center: f32, q: f32, sample_rate: usize) -> Self {
+        let (cos, alpha) = Self::parameters(center, q, sample_rate);
+        Self::new([alpha, 0.0, -alpha], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    // Removes a narrow band around the center, such as mains hum.
+    pub fn notch(center: f32, q: f32, sample_rate: usize) -> Self {
+        let (cos, alpha) = Self::parameters(center, q, sample_rate);
+        Self::new(
+            [1.0, -2.0 * cos, 1.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    // Gain of the filter at `frequency`, as a plain ratio.
+    pub fn response(&self, frequency: f32, sample_rate: usize) -> f32 {
+        let omega = TAU * frequency as f64 / sample_rate as f64;
+        // Evaluates a + b e^-jω + c e^-2jω.
+        let evaluate = |c: [f64; 3]| {
+            let re = c[0] + c[1] * omega.cos() + c[2] * (2.0 * omega).cos();
+            let im = -c[1] * omega.sin() - c[2] * (2.0 * omega).sin();
+            (re * re + im * im).sqrt()
+        };
+        let numerator = evaluate(self.b);
+        let denominator = evaluate([1.0, self.a[0], self.a[1]]);
+        (numerator / denominator) as f32
+    }
+}
+
+impl Processor for Biquad {
+    fn process(&mut self, samples: &mut [f32]) {
+        let [b0, b1, b2] = self.b;
+        let [a1, a2] = self.a;
+        for sample in samples {
+            let x = *sample as f64;
+            let y = b0 * x + self.state[0];
+            self.state[0] = b1 * x - a1 * y + self.state[1];
+            self.state[1] = b2 * x - a2 * y;
+            *sample = y as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 44100;
+
+    fn db(gain: f32) -> f32 {
+        20.0 * gain.log10()
+    }
+
+    #[test]
+    fn low_pass_response() {
+        let biquad = Biquad::low_pass(1000.0, 0.707, SAMPLE_RATE);
+        assert!(db(biquad.response(100.0, SAMPLE_RATE)).abs() < 0.01);
+        assert!((db(biquad.response(1000.0, SAMPLE_RATE)) + 3.0).abs() < 0.05);
+        // 12 dB an octave, over more than three octaves.
+        assert!(db(biquad.response(10000.0, SAMPLE_RATE)) < -40.0);
+    }
+
+    #[test]
+    fn high_pass_response() {
+        let biquad = Biquad::high_pass(1000.0, 0.707, SAMPLE_RATE);
+        assert!(db(biquad.response(100.0, SAMPLE_RATE)) < -39.0);
+        assert!((db(biquad.response(1000.0, SAMPLE_RATE)) + 3.0).abs() < 0.05);
+        assert!(db(biquad.response(10000.0, SAMPLE_RATE)).abs() < 0.01);
+    }
+
+    #[test]
+    fn band_pass_response() {
+        let biquad = Biquad::band_pass(1000.0, 2.0, SAMPLE_RATE);
+        assert!(db(biquad.response(100.0, SAMPLE_RATE)) < -25.0);
+        assert!(db(biquad.response(1000.0, SAMPLE_RATE)).abs() < 0.01);
+        assert!(db(biquad.response(10000.0, SAMPLE_RATE)) < -25.0);
+    }
+
+    #[test]
+    fn notch_response() {
+        let biquad = Biquad::notch(60.0, 10.0, SAMPLE_RATE);
+        assert!(db(biquad.response(60.0, SAMPLE_RATE)) < -100.0);
+        assert!(db(biquad.response(20.0, SAMPLE_RATE)).abs() < 0.01);
+        assert!(db(biquad.response(1000.0, SAMPLE_RATE)).abs() < 0.01);
+    }
+
+    #[test]
+    fn state_carries_across_blocks() {
+        let mut impulse = vec![0.0; 200];
+        impulse[0] = 1.0;
+
+        let mut whole = impulse.clone();
+        Biquad::low_pass(1000.0, 0.707, SAMPLE_RATE).process(&mut whole);
+
+        let mut biquad = Biquad::low_pass(1000.0, 0.707, SAMPLE_RATE);
+        let (first, second) = impulse.split_at_mut(3);
+        biquad.process(first);
+        biquad.process(second);
+
+        assert_eq!(impulse, whole);
+    }
+
+    #[test]
+    fn filtering_matches_the_response() {
+        let mut biquad = Biquad::low_pass(1000.0, 0.707, SAMPLE_RATE);
+        let mut samples: Vec<f32> = (0..SAMPLE_RATE)
+            .map(|i| (TAU * 1000.0 * i as f64 / SAMPLE_RATE as f64).sin() as f32)
+            .collect();
+        biquad.process(&mut samples);
+
+        // Past the start, where the filter is still settling.
+        let peak = samples[SAMPLE_RATE / 2..]
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!((peak - biquad.response(1000.0, SAMPLE_RATE)).abs() < 0.001);
+    }
+}
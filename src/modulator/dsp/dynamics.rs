@@ -0,0 +1,309 @@
+use super::Processor;
+use std::collections::VecDeque;
+
+// Attack and release times throughout are in seconds, and levels are in dBFS.
+
+// Per-sample smoothing coefficient for a one-pole filter with the given time constant.
+fn coefficient(time: f32, sample_rate: usize) -> f32 {
+    if time <= 0.0 {
+        return 0.0;
+    }
+    (-1.0 / (time * sample_rate as f32)).exp()
+}
+
+fn to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-10).log10()
+}
+
+fn from_db(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+// Moves `value` towards `target`, with separate speeds for going down and up.
+fn smooth(value: f32, target: f32, attack: f32, release: f32) -> f32 {
+    let coefficient = if target < value { attack } else { release };
+    target + (value - target) * coefficient
+}
+
+// Turns everything above the threshold down by the ratio, going by the RMS level.
+pub struct Compressor {
+    threshold: f32,
+    ratio: f32,
+    makeup: f32,
+    attack: f32,
+    release: f32,
+    // Mean square of the input, smoothed by the attack and release.
+    mean_square: f32,
+}
+
+impl Compressor {
+    pub fn new(threshold: f32, ratio: f32, attack: f32, release: f32, sample_rate: usize) -> Self {
+        Self {
+            threshold,
+            ratio: ratio.max(1.0),
+            makeup: 1.0,
+            attack: coefficient(attack, sample_rate),
+            release: coefficient(release, sample_rate),
+            mean_square: 0.0,
+        }
+    }
+
+    // Gain in dB applied after compression, to bring the level back up.
+    pub fn set_makeup_gain(&mut self, makeup: f32) {
+        self.makeup = from_db(makeup);
+    }
+}
+
+impl Processor for Compressor {
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            // The attack applies while the level is rising.
+            let square = *sample * *sample;
+            let coefficient = if square > self.mean_square {
+                self.attack
+            } else {
+                self.release
+            };
+            self.mean_square = square + (self.mean_square - square) * coefficient;
+
+            // Mean square to dB is 10 log10 rather than 20.
+            let level = 10.0 * self.mean_square.max(1e-20).log10();
+            let over = (level - self.threshold).max(0.0);
+            let reduction = over - over / self.ratio;
+            *sample *= from_db(-reduction) * self.makeup;
+        }
+    }
+}
+
+// Keeps peaks from ever going past the ceiling. It looks ahead by delaying the audio, so the
+// gain has already come down smoothly by the time a peak comes out.
+pub struct Limiter {
+    ceiling: f32,
+    release: f32,
+    lookahead: usize,
+    // The input, delayed by lookahead - 1 samples.
+    delay: VecDeque<f32>,
+    // Gains that the last `lookahead` input samples need to stay under the ceiling, as sample
+    // number and gain. Only those lower than every gain after them are kept, so the front is
+    // always the lowest.
+    needed: VecDeque<(u64, f32)>,
+    samples: u64,
+    // The lowest of `needed` for each of the last `lookahead` samples, and their sum.
+    held: VecDeque<f32>,
+    held_sum: f64,
+    gain: f32,
+}
+
+impl Limiter {
+    pub fn new(ceiling: f32, lookahead: f32, release: f32, sample_rate: usize) -> Self {
+        let lookahead = ((lookahead * sample_rate as f32).round() as usize).max(1);
+        Self {
+            ceiling: from_db(ceiling),
+            release: coefficient(release, sample_rate),
+            lookahead,
+            delay: VecDeque::from(vec![0.0; lookahead - 1]),
+            needed: VecDeque::with_capacity(lookahead),
+            samples: 0,
+            held: VecDeque::from(vec![1.0; lookahead]),
+            held_sum: lookahead as f64,
+            gain: 1.0,
+        }
+    }
+}
+
+impl Processor for Limiter {
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            self.delay.push_back(*sample);
+            let needed = (self.ceiling / sample.abs()).min(1.0);
+            while self.needed.back().is_some_and(|&(_, gain)| gain >= needed) {
+                self.needed.pop_back();
+            }
+            self.needed.push_back((self.samples, needed));
+            if self.needed[0].0 + self.lookahead as u64 <= self.samples {
+                self.needed.pop_front();
+            }
+            self.samples += 1;
+
+            // Holding the minimum for the lookahead and then averaging over the lookahead
+            // gives a ramp that reaches each peak's gain right as that peak comes out.
+            let held = self.needed[0].1;
+            self.held_sum += (held - self.held.pop_front().unwrap()) as f64;
+            self.held.push_back(held);
+            let ramp = (self.held_sum / self.lookahead as f64) as f32;
+
+            self.gain = ramp.min(smooth(self.gain, 1.0, 0.0, self.release));
+            *sample = self.delay.pop_front().unwrap() * self.gain;
+        }
+    }
+}
+
+// Automatic gain control: slowly rides the gain so the RMS level sits at the target.
+// Below the gate, the input is taken to be silence and the gain is left alone rather than
+// turned all the way up.
+pub struct Agc {
+    target: f32,
+    max_gain: f32,
+    gate: f32,
+    // Averaging time of the level measurement.
+    window: f32,
+    attack: f32,
+    release: f32,
+    mean_square: f32,
+    gain: f32,
+}
+
+impl Agc {
+    pub fn new(target: f32, attack: f32, release: f32, sample_rate: usize) -> Self {
+        Self {
+            target: from_db(target),
+            max_gain: from_db(30.0),
+            gate: from_db(-60.0),
+            window: coefficient(0.4, sample_rate),
+            attack: coefficient(attack, sample_rate),
+            release: coefficient(release, sample_rate),
+            mean_square: 0.0,
+            gain: 1.0,
+        }
+    }
+
+    // The most the gain may go up by, in dB. Defaults to 30 dB.
+    pub fn set_max_gain(&mut self, max_gain: f32) {
+        self.max_gain = from_db(max_gain);
+    }
+
+    // Level below which the gain is frozen. Defaults to -60 dBFS.
+    pub fn set_gate(&mut self, gate: f32) {
+        self.gate = from_db(gate);
+    }
+
+    // The current gain, in dB.
+    pub fn gain(&self) -> f32 {
+        to_db(self.gain)
+    }
+}
+
+impl Processor for Agc {
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let square = *sample * *sample;
+            self.mean_square = square + (self.mean_square - square) * self.window;
+
+            let level = self.mean_square.sqrt();
+            if level > self.gate {
+                let wanted = (self.target / level).min(self.max_gain);
+                self.gain = smooth(self.gain, wanted, self.attack, self.release);
+            }
+            *sample *= self.gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::uniform;
+
+    const SAMPLE_RATE: usize = 44100;
+
+    // A square wave, whose mean square is the same from one sample to the next, so that
+    // detected levels settle without any ripple.
+    fn square(db: f32, len: usize) -> Vec<f32> {
+        let amplitude = from_db(db);
+        (0..len)
+            .map(|i| if i % 50 < 25 { amplitude } else { -amplitude })
+            .collect()
+    }
+
+    fn peak_db(samples: &[f32]) -> f32 {
+        to_db(
+            samples
+                .iter()
+                .fold(0.0, |peak, sample| sample.abs().max(peak)),
+        )
+    }
+
+    #[test]
+    fn compressor_turns_down_by_the_ratio_above_the_threshold() {
+        let mut compressor = Compressor::new(-20.0, 4.0, 0.01, 0.1, SAMPLE_RATE);
+        // Settles within well under a second, then the last stretch is measured.
+        let level = |compressor: &mut Compressor, db| {
+            let mut samples = square(db, SAMPLE_RATE);
+            compressor.process(&mut samples);
+            peak_db(&samples[SAMPLE_RATE / 2..])
+        };
+        assert!((level(&mut compressor, -30.0) + 30.0).abs() < 0.01);
+        assert!((level(&mut compressor, -8.0) + 17.0).abs() < 0.01);
+        assert!((level(&mut compressor, 0.0) + 15.0).abs() < 0.01);
+
+        compressor.set_makeup_gain(6.0);
+        assert!((level(&mut compressor, -8.0) + 11.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn compressor_attacks_faster_than_it_releases() {
+        let mut compressor = Compressor::new(-20.0, 4.0, 0.005, 0.2, SAMPLE_RATE);
+        let mut quiet = square(-30.0, SAMPLE_RATE);
+        compressor.process(&mut quiet);
+
+        // One attack time into a loud stretch most of the reduction is in place, while one
+        // attack time back into a quiet stretch most of it still is.
+        let mut loud = square(0.0, SAMPLE_RATE);
+        compressor.process(&mut loud);
+        let attack = SAMPLE_RATE / 200;
+        assert!(peak_db(&loud[attack * 3..attack * 4]) < -10.0);
+        compressor.process(&mut quiet);
+        assert!(peak_db(&quiet[attack * 3..attack * 4]) < -35.0);
+    }
+
+    #[test]
+    fn limiter_keeps_to_its_ceiling() {
+        let mut limiter = Limiter::new(-6.0, 0.005, 0.05, SAMPLE_RATE);
+        // Noise with isolated peaks up to 4 times full scale.
+        let mut samples: Vec<f32> = (0..SAMPLE_RATE as u64)
+            .map(|i| {
+                let noise = 0.3 * uniform(1, i);
+                if i % 997 == 0 {
+                    noise + 4.0 * uniform(2, i)
+                } else {
+                    noise
+                }
+            })
+            .collect();
+        let input = samples.clone();
+        limiter.process(&mut samples);
+        assert!(peak_db(&samples) <= -6.0 + 1e-4, "{}", peak_db(&samples));
+
+        // Once peaks stop and the gain has recovered, everything comes out as it went in, a
+        // lookahead later.
+        let mut quiet: Vec<f32> = input.iter().map(|sample| sample.clamp(-0.3, 0.3)).collect();
+        limiter.process(&mut quiet);
+        let delay = (0.005 * SAMPLE_RATE as f32).round() as usize - 1;
+        let settled = SAMPLE_RATE / 2;
+        for (output, input) in quiet[settled..].iter().zip(&input[settled - delay..]) {
+            assert!((output - input.clamp(-0.3, 0.3)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn agc_rides_the_gain_to_the_target() {
+        let mut agc = Agc::new(-20.0, 0.05, 0.5, SAMPLE_RATE);
+        let settle = |agc: &mut Agc, db| {
+            let mut samples = square(db, 10 * SAMPLE_RATE);
+            agc.process(&mut samples);
+            agc.gain()
+        };
+
+        assert!((settle(&mut agc, -30.0) - 10.0).abs() < 0.1);
+        assert!((settle(&mut agc, -5.0) + 15.0).abs() < 0.1);
+        // Held back by the maximum gain.
+        assert!((settle(&mut agc, -55.0) - 30.0).abs() < 0.1);
+        agc.set_max_gain(20.0);
+        assert!((settle(&mut agc, -55.0) - 20.0).abs() < 0.1);
+        // Below the gate the gain stays where it was.
+        assert!((settle(&mut agc, -70.0) - 20.0).abs() < 0.1);
+        agc.set_gate(-80.0);
+        assert!((settle(&mut agc, -70.0) - 20.0).abs() < 0.1);
+        assert!((settle(&mut agc, -30.0) - 10.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,154 @@
+use super::Processor;
+use crate::modulator::pcm::{kaiser, sinc};
+
+// Kaiser window shape parameter for the filters designed here, giving roughly 80 dB of
+// stopband attenuation.
+const KAISER_BETA: f64 = 8.0;
+
+// Finite impulse response filter. The designed filters are linear phase, which delays the
+// audio by (taps - 1) / 2 samples.
+pub struct Fir {
+    coefficients: Vec<f32>,
+    // The last taps - 1 input samples, oldest first.
+    history: Vec<f32>,
+}
+
+impl Fir {
+    pub fn new(coefficients: Vec<f32>) -> Self {
+        let history = vec![0.0; coefficients.len().saturating_sub(1)];
+        Self {
+            coefficients,
+            history,
+        }
+    }
+
+    // Windowed-sinc low-pass. More taps give a steeper transition band.
+    pub fn low_pass(cutoff: f32, taps: usize, sample_rate: usize) -> Self {
+        Self::new(design_low_pass(cutoff, taps, sample_rate))
+    }
+
+    // Spectral inversion of the low-pass: an impulse minus the low-pass.
+    pub fn high_pass(cutoff: f32, taps: usize, sample_rate: usize) -> Self {
+        let mut coefficients = design_low_pass(cutoff, taps, sample_rate);
+        for c in &mut coefficients {
+            *c = -*c;
+        }
+        let middle = coefficients.len() / 2;
+        coefficients[middle] += 1.0;
+        Self::new(coefficients)
+    }
+
+    // The difference between low-passes at the two edges of the band.
+    pub fn band_pass(low: f32, high: f32, taps: usize, sample_rate: usize) -> Self {
+        let lower = design_low_pass(low, taps, sample_rate);
+        let upper = design_low_pass(high, taps, sample_rate);
+        Self::new(upper.iter().zip(&lower).map(|(u, l)| u - l).collect())
+    }
+
+    // Gain of the filter at `frequency`, as a plain ratio.
+    pub fn response(&self, frequency: f32, sample_rate: usize) -> f32 {
+        let omega = std::f64::consts::TAU * frequency as f64 / sample_rate as f64;
+        let (re, im) =
+            self.coefficients
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (n, &c)| {
+                    let angle = omega * n as f64;
+                    (re + c as f64 * angle.cos(), im - c as f64 * angle.sin())
+                });
+        (re * re + im * im).sqrt() as f32
+    }
+}
+
+// An odd number of taps puts a tap right in the middle, so the filter is symmetric around it.
+fn design_low_pass(cutoff: f32, taps: usize, sample_rate: usize) -> Vec<f32> {
+    let taps = taps.max(1) | 1;
+    let half = (taps / 2) as f64;
+    let cutoff = cutoff as f64 / sample_rate as f64;
+
+    let coefficients: Vec<f64> = (0..taps)
+        .map(|tap| {
+            let x = tap as f64 - half;
+            2.0 * cutoff * sinc(2.0 * cutoff * x) * kaiser(x / (half + 1.0), KAISER_BETA)
+        })
+        .collect();
+
+    // Normalize to unity gain at DC.
+    let sum: f64 = coefficients.iter().sum();
+    coefficients.iter().map(|&c| (c / sum) as f32).collect()
+}
+
+impl Processor for Fir {
+    fn process(&mut self, samples: &mut [f32]) {
+        let history_len = self.history.len();
+        let mut input = std::mem::take(&mut self.history);
+        input.extend_from_slice(samples);
+
+        for (i, sample) in samples.iter_mut().enumerate() {
+            // Coefficient n applies to the input from n samples ago.
+            *sample = self
+                .coefficients
+                .iter()
+                .zip(input[i..=i + history_len].iter().rev())
+                .map(|(c, x)| c * x)
+                .sum();
+        }
+
+        input.drain(..input.len() - history_len);
+        self.history = input;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 44100;
+
+    fn db(gain: f32) -> f32 {
+        20.0 * gain.log10()
+    }
+
+    #[test]
+    fn low_pass_response() {
+        let fir = Fir::low_pass(4000.0, 101, SAMPLE_RATE);
+        assert!(db(fir.response(1000.0, SAMPLE_RATE)).abs() < 0.05);
+        assert!(db(fir.response(3000.0, SAMPLE_RATE)).abs() < 0.05);
+        assert!(db(fir.response(6000.0, SAMPLE_RATE)) < -75.0);
+        assert!(db(fir.response(15000.0, SAMPLE_RATE)) < -75.0);
+    }
+
+    #[test]
+    fn high_pass_response() {
+        let fir = Fir::high_pass(4000.0, 101, SAMPLE_RATE);
+        assert!(db(fir.response(100.0, SAMPLE_RATE)) < -75.0);
+        assert!(db(fir.response(2000.0, SAMPLE_RATE)) < -75.0);
+        assert!(db(fir.response(6000.0, SAMPLE_RATE)).abs() < 0.05);
+        assert!(db(fir.response(15000.0, SAMPLE_RATE)).abs() < 0.05);
+    }
+
+    #[test]
+    fn band_pass_response() {
+        let fir = Fir::band_pass(1000.0, 6000.0, 101, SAMPLE_RATE);
+        // Below the band both low-passes pass, so how far down it gets depends on how closely
+        // their passbands match.
+        assert!(db(fir.response(50.0, SAMPLE_RATE)) < -60.0);
+        assert!(db(fir.response(3500.0, SAMPLE_RATE)).abs() < 0.05);
+        assert!(db(fir.response(9000.0, SAMPLE_RATE)) < -75.0);
+    }
+
+    #[test]
+    fn state_carries_across_blocks() {
+        let mut fir = Fir::low_pass(4000.0, 101, SAMPLE_RATE);
+        let mut impulse = vec![0.0; 150];
+        impulse[0] = 1.0;
+
+        let (first, second) = impulse.split_at_mut(40);
+        fir.process(first);
+        fir.process(second);
+
+        // The impulse response of an FIR filter is its coefficients.
+        assert_eq!(&impulse[..101], &fir.coefficients[..]);
+        assert!(impulse[101..].iter().all(|&sample| sample == 0.0));
+    }
+}
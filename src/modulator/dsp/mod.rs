@@ -0,0 +1,22 @@
+mod biquad;
+mod dynamics;
+mod fir;
+
+pub use biquad::Biquad;
+pub use dynamics::{Agc, Compressor, Limiter};
+pub use fir::Fir;
+
+// A stage that audio passes through on its way from a loader to the modulators.
+// Samples come in blocks as they're read, and anything a processor remembers between samples
+// carries over from one block to the next.
+pub trait Processor: Send {
+    fn process(&mut self, samples: &mut [f32]);
+}
+
+impl Processor for Vec<Box<dyn Processor>> {
+    fn process(&mut self, samples: &mut [f32]) {
+        for processor in self {
+            processor.process(samples);
+        }
+    }
+}
@@ -1,9 +1,27 @@
-use super::{Phase, Signal};
+use super::{Phase, Signal, CHUNK};
 use crate::modulator::IntSignal;
+use std::num::Wrapping;
 use std::sync::Arc;
 
 pub trait FmCarrier: Send + Sync {
     fn sample_with_deviation(&self, total_index: u32, deviation: Phase) -> f32;
+
+    // Block version of sample_with_deviation, with one deviation for every sample.
+    fn fill_with_deviation(&self, start: u32, deviations: &[Phase], samples: &mut [f32]) {
+        for ((total_index, &deviation), sample) in (start..).zip(deviations).zip(samples) {
+            *sample = self.sample_with_deviation(total_index, deviation);
+        }
+    }
+}
+
+impl<C: FmCarrier + ?Sized> FmCarrier for Arc<C> {
+    fn sample_with_deviation(&self, total_index: u32, deviation: Phase) -> f32 {
+        (**self).sample_with_deviation(total_index, deviation)
+    }
+
+    fn fill_with_deviation(&self, start: u32, deviations: &[Phase], samples: &mut [f32]) {
+        (**self).fill_with_deviation(start, deviations, samples)
+    }
 }
 
 #[derive(Clone)]
@@ -12,12 +30,48 @@ pub struct FrequencyModulator {
     pub information: Arc<dyn IntSignal>,
 }
 
+const MAX_DEVIATION: u32 = 37500;
+
 impl Signal for FrequencyModulator {
     fn sample(&self, total_index: u32) -> f32 {
-        let max_deviation = 37500;
-
-        let deviation = self.information.sample(total_index) * max_deviation;
+        let deviation = self.information.sample(total_index) * MAX_DEVIATION;
 
         self.carrier.sample_with_deviation(total_index, deviation)
     }
+
+    fn fill(&self, start: u32, samples: &mut [f32]) {
+        let mut deviations = [Phase(Wrapping(0)); CHUNK];
+        for (start, samples) in (start..).step_by(CHUNK).zip(samples.chunks_mut(CHUNK)) {
+            let deviations = &mut deviations[..samples.len()];
+            self.information.fill(start, deviations);
+            for deviation in deviations.iter_mut() {
+                *deviation = *deviation * MAX_DEVIATION;
+            }
+
+            self.carrier.fill_with_deviation(start, deviations, samples)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::{PhaseShift, Sine, SineWave};
+    use crate::DOT_CLOCK;
+
+    #[test]
+    fn fill_matches_sample_across_chunks() {
+        let carrier = Sine::new(SineWave, 10_000_000, DOT_CLOCK);
+        let modulator = FrequencyModulator {
+            carrier: Arc::new(PhaseShift::new(carrier, 0.25)),
+            information: Arc::new(Sine::new(SineWave, 1000, DOT_CLOCK)),
+        };
+
+        let start = 12345;
+        let mut samples = vec![0.0; 3 * CHUNK + 7];
+        modulator.fill(start, &mut samples);
+        for (total_index, &sample) in (start..).zip(&samples) {
+            assert_eq!(sample, Signal::sample(&modulator, total_index));
+        }
+    }
 }
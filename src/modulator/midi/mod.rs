@@ -0,0 +1,124 @@
+mod smf;
+mod synth;
+
+use std::error::Error;
+use std::num::Wrapping;
+use std::path::Path;
+
+pub use synth::{Envelope, Timbre};
+
+use super::phase::{Phase, PhaseAccumulator};
+use super::{integrate, IntSignal, Interpolation, Pcm, Signal, Window};
+use crate::VERTICAL_SYNC;
+use smf::Event;
+use synth::Synth;
+
+// Plays a Standard MIDI File through a small built-in synthesizer, one frame at a time.
+// Like the PCM loaders, the audio it renders can be used directly or pre-integrated for FM.
+pub struct MidiPlayer {
+    events: Vec<Event>,
+    next_event: usize,
+    synth: Synth,
+    sample_rate: usize,
+    // Samples rendered since the song last started, used to time its events.
+    rendered: u64,
+    looping: bool,
+    interpolation: Interpolation,
+    // Rendered samples around the current frame.
+    window: Window,
+    starting_angle: PhaseAccumulator,
+}
+
+impl MidiPlayer {
+    pub fn open<P: AsRef<Path>>(path: P, sample_rate: usize) -> Result<Self, Box<dyn Error>> {
+        let events = smf::parse(&std::fs::read(path)?)?;
+        let samples_per_frame = (sample_rate as f64 / VERTICAL_SYNC).round() as usize;
+
+        let mut player = Self {
+            events,
+            next_event: 0,
+            synth: Synth::new(sample_rate),
+            sample_rate,
+            rendered: 0,
+            looping: false,
+            interpolation: Interpolation::Nearest,
+            window: Window::new(samples_per_frame),
+            starting_angle: PhaseAccumulator::new(Phase(Wrapping(0))),
+        };
+        player.fill_window();
+
+        Ok(player)
+    }
+
+    pub fn next_frame(&mut self) {
+        self.window.next_frame();
+        self.fill_window();
+    }
+
+    fn fill_window(&mut self) {
+        for _ in 0..self.window.missing() {
+            let sample = self.render_sample();
+            self.window.samples.push_back(sample);
+        }
+    }
+
+    fn render_sample(&mut self) -> f32 {
+        while let Some(event) = self.events.get(self.next_event) {
+            if (event.time * self.sample_rate as f64).round() as u64 > self.rendered {
+                break;
+            }
+            self.synth.handle(event.channel, &event.message);
+            self.next_event += 1;
+        }
+        if self.looping && self.is_finished() {
+            self.next_event = 0;
+            self.rendered = 0;
+        }
+
+        self.rendered += 1;
+        self.synth.render_sample()
+    }
+
+    fn pcm(&self) -> Pcm {
+        self.window.pcm(self.sample_rate)
+    }
+
+    pub fn samples(&self) -> Box<dyn Signal> {
+        self.interpolation.interpolate(self.pcm())
+    }
+
+    // The integral of the audio, for use as FM information.
+    // Call once per frame, since the phase carries on from the previous call.
+    pub fn integrated_samples(&mut self) -> Box<dyn IntSignal> {
+        integrate(self.pcm(), &self.interpolation, &mut self.starting_angle)
+    }
+
+    pub fn set_interp(&mut self, method: Interpolation) {
+        self.window.fit(&method);
+        self.interpolation = method;
+        self.fill_window();
+    }
+
+    pub fn set_timbre(&mut self, timbre: Timbre) {
+        self.synth.timbre = timbre;
+    }
+
+    pub fn set_envelope(&mut self, envelope: Envelope) {
+        self.synth.envelope = envelope;
+    }
+
+    // Scales the mix of every playing note. The default leaves headroom for a few at once.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.synth.gain = gain;
+    }
+
+    // Starts the song over once it ends, rather than going silent.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    // True once every event has been played and every note has died away.
+    pub fn is_finished(&self) -> bool {
+        self.next_event == self.events.len() && self.synth.is_silent()
+    }
+}
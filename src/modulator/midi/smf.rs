@@ -0,0 +1,355 @@
+use std::error::Error;
+
+pub(super) enum Message {
+    NoteOn { key: u8, velocity: u8 },
+    NoteOff { key: u8 },
+    Volume(u8),
+    // -8192..=8191, with 0 being no bend.
+    PitchBend(i16),
+    AllNotesOff,
+}
+
+pub(super) struct Event {
+    // Seconds since the start of the song.
+    pub(super) time: f64,
+    pub(super) channel: u8,
+    pub(super) message: Message,
+}
+
+// Microseconds per quarter note until the first tempo event, which is 120 BPM.
+const DEFAULT_TEMPO: u32 = 500_000;
+
+enum TrackEvent {
+    Tempo(u32),
+    Channel(u8, Message),
+}
+
+enum Division {
+    TicksPerQuarter(u16),
+    TicksPerSecond(f64),
+}
+
+// Parses a format 0 or format 1 file, applying its tempo map to give every event a time.
+pub(super) fn parse(bytes: &[u8]) -> Result<Vec<Event>, Box<dyn Error>> {
+    let mut cursor = Cursor { bytes, position: 0 };
+
+    let (kind, mut header) = cursor.chunk()?;
+    if kind != b"MThd" {
+        return Err("not a Standard MIDI File".into());
+    }
+    let format = header.u16()?;
+    let track_count = header.u16()?;
+    let division = header.u16()?;
+    if format > 1 {
+        return Err(format!("unsupported MIDI file format {}", format).into());
+    }
+    if division & 0x7fff == 0 {
+        return Err("MIDI file has no time division".into());
+    }
+    let division = if division & 0x8000 != 0 {
+        // SMPTE timing: the high byte is minus the frames per second, which can be as low
+        // as -128.
+        let frames_per_second = -((division >> 8) as i8 as i16) as f64;
+        let ticks_per_frame = (division & 0xff) as f64;
+        if ticks_per_frame == 0.0 {
+            return Err("MIDI file has no ticks per SMPTE frame".into());
+        }
+        Division::TicksPerSecond(frames_per_second * ticks_per_frame)
+    } else {
+        Division::TicksPerQuarter(division)
+    };
+
+    // Every track's events, tagged with their tick and kept in file order within a tick.
+    let mut events: Vec<(u64, TrackEvent)> = Vec::new();
+    let mut tracks_found = 0;
+    while tracks_found < track_count && !cursor.is_empty() {
+        let (kind, track) = cursor.chunk()?;
+        // Unknown chunk types are to be skipped over.
+        if kind == b"MTrk" {
+            parse_track(track, &mut events)?;
+            tracks_found += 1;
+        }
+    }
+    // A stable sort, so simultaneous events keep their order.
+    events.sort_by_key(|(tick, _)| *tick);
+
+    let mut tempo = DEFAULT_TEMPO;
+    let mut last_tick = 0;
+    let mut time = 0.0;
+    let mut timed = Vec::new();
+    for (tick, event) in events {
+        time += match division {
+            Division::TicksPerQuarter(ticks) => {
+                (tick - last_tick) as f64 * tempo as f64 / 1_000_000.0 / ticks as f64
+            }
+            Division::TicksPerSecond(ticks) => (tick - last_tick) as f64 / ticks,
+        };
+        last_tick = tick;
+
+        match event {
+            TrackEvent::Tempo(new_tempo) => tempo = new_tempo,
+            TrackEvent::Channel(channel, message) => timed.push(Event {
+                time,
+                channel,
+                message,
+            }),
+        }
+    }
+
+    Ok(timed)
+}
+
+fn parse_track(
+    mut track: Cursor,
+    events: &mut Vec<(u64, TrackEvent)>,
+) -> Result<(), Box<dyn Error>> {
+    let mut tick = 0;
+    let mut running_status = None;
+    while !track.is_empty() {
+        tick += track.variable_length()? as u64;
+
+        let status = match track.peek()? {
+            data if data < 0x80 => running_status.ok_or("running status without a status")?,
+            status => {
+                track.u8()?;
+                status
+            }
+        };
+
+        match status {
+            0xff => {
+                let kind = track.u8()?;
+                let len = track.variable_length()?;
+                let data = track.bytes(len as usize)?;
+                match (kind, data) {
+                    // End of track.
+                    (0x2f, _) => break,
+                    (0x51, &[a, b, c]) => {
+                        let tempo = u32::from_be_bytes([0, a, b, c]);
+                        events.push((tick, TrackEvent::Tempo(tempo)));
+                    }
+                    _ => {}
+                }
+                running_status = None;
+            }
+            0xf0 | 0xf7 => {
+                let len = track.variable_length()?;
+                track.bytes(len as usize)?;
+                running_status = None;
+            }
+            _ => {
+                running_status = Some(status);
+                let channel = status & 0x0f;
+                let message = match status & 0xf0 {
+                    0x80 => {
+                        let key = track.u8()?;
+                        track.u8()?;
+                        Some(Message::NoteOff { key })
+                    }
+                    0x90 => {
+                        let key = track.u8()?;
+                        let velocity = track.u8()?;
+                        // A note on with no velocity is how running status sends note offs.
+                        Some(match velocity {
+                            0 => Message::NoteOff { key },
+                            _ => Message::NoteOn { key, velocity },
+                        })
+                    }
+                    0xb0 => {
+                        let controller = track.u8()?;
+                        let value = track.u8()?;
+                        match controller {
+                            7 => Some(Message::Volume(value)),
+                            120 | 123 => Some(Message::AllNotesOff),
+                            _ => None,
+                        }
+                    }
+                    0xe0 => {
+                        let low = track.u8()? as i16;
+                        let high = track.u8()? as i16;
+                        Some(Message::PitchBend((high << 7 | low) - 8192))
+                    }
+                    // Aftertouch.
+                    0xa0 => {
+                        track.bytes(2)?;
+                        None
+                    }
+                    // Program change and channel pressure.
+                    0xc0 | 0xd0 => {
+                        track.u8()?;
+                        None
+                    }
+                    _ => return Err(format!("unknown MIDI status byte {:#x}", status).into()),
+                };
+                if let Some(message) = message {
+                    events.push((tick, TrackEvent::Channel(channel, message)));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + len)
+            .ok_or("unexpected end of MIDI data")?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn peek(&self) -> Result<u8, Box<dyn Error>> {
+        Ok(*self
+            .bytes
+            .get(self.position)
+            .ok_or("unexpected end of MIDI data")?)
+    }
+
+    fn u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Box<dyn Error>> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    // Big-endian, seven bits per byte, with the top bit set on every byte but the last.
+    fn variable_length(&mut self) -> Result<u32, Box<dyn Error>> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = value << 7 | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("variable length quantity is too long".into())
+    }
+
+    // Splits off the next chunk as its type and a cursor over its contents.
+    fn chunk(&mut self) -> Result<(&'a [u8], Cursor<'a>), Box<dyn Error>> {
+        let kind = self.bytes(4)?;
+        let len = self.u32()? as usize;
+        // Some files in the wild claim a longer last chunk than they actually have.
+        let len = len.min(self.bytes.len() - self.position);
+        let bytes = self.bytes(len)?;
+
+        Ok((kind, Cursor { bytes, position: 0 }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(format: u16, division: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend(6u32.to_be_bytes());
+        bytes.extend(format.to_be_bytes());
+        bytes.extend((tracks.len() as u16).to_be_bytes());
+        bytes.extend(division.to_be_bytes());
+        for track in tracks {
+            bytes.extend(b"MTrk");
+            bytes.extend((track.len() as u32).to_be_bytes());
+            bytes.extend(*track);
+        }
+        bytes
+    }
+
+    // (time, channel, key, true for note on) for every note event.
+    fn notes(events: &[Event]) -> Vec<(f64, u8, u8, bool)> {
+        events
+            .iter()
+            .filter_map(|event| match event.message {
+                Message::NoteOn { key, .. } => Some((event.time, event.channel, key, true)),
+                Message::NoteOff { key } => Some((event.time, event.channel, key, false)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn running_status_repeats_the_last_status() {
+        let track = [
+            0x00, 0x91, 60, 100, // note on
+            0x00, 64, 100, // note on, by running status
+            0x60, 60, 0, // note off as a note on with no velocity
+            0x00, 0x81, 64, 0, // note off
+            0x00, 0xff, 0x2f, 0x00,
+        ];
+        let events = parse(&file(0, 96, &[&track])).unwrap();
+        assert_eq!(
+            notes(&events),
+            [
+                (0.0, 1, 60, true),
+                (0.0, 1, 64, true),
+                (0.5, 1, 60, false),
+                (0.5, 1, 64, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn running_status_needs_a_status_first() {
+        let track = [0x00, 60, 100, 0x00, 0xff, 0x2f, 0x00];
+        assert!(parse(&file(0, 96, &[&track])).is_err());
+    }
+
+    #[test]
+    fn tempo_map_applies_to_every_track() {
+        // Format 1 keeps the tempo map in its own track. 120 BPM, then 240 BPM from the second
+        // beat on.
+        let tempo = [
+            0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20, // 500000 us per quarter
+            0x60, 0xff, 0x51, 0x03, 0x03, 0xd0, 0x90, // 250000 us per quarter
+            0x00, 0xff, 0x2f, 0x00,
+        ];
+        let first = [
+            0x60, 0x90, 60, 100, // beat 2
+            0x81, 0x40, 0x80, 60, 0, // beat 4
+            0x00, 0xff, 0x2f, 0x00,
+        ];
+        let second = [
+            0x30, 0x92, 72, 100, // half a beat in
+            0x82, 0x20, 0x82, 72, 0, // beat 4 and a half
+            0x00, 0xff, 0x2f, 0x00,
+        ];
+        let events = parse(&file(1, 96, &[&tempo, &first, &second])).unwrap();
+        assert_eq!(
+            notes(&events),
+            [
+                (0.25, 2, 72, true),
+                (0.5, 0, 60, true),
+                (1.0, 0, 60, false),
+                (1.125, 2, 72, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn smpte_division_counts_ticks_per_second() {
+        let track = [0x84, 0x00, 0x90, 60, 100, 0x00, 0xff, 0x2f, 0x00];
+        // -128 frames per second, which overflows an i8 once negated, at 4 ticks a frame.
+        let events = parse(&file(0, 0x8004, &[&track])).unwrap();
+        assert_eq!(notes(&events), [(1.0, 0, 60, true)]);
+
+        // -25 frames per second at 0 ticks a frame.
+        assert!(parse(&file(0, 0xe700, &[&track])).is_err());
+    }
+}
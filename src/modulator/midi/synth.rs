@@ -0,0 +1,271 @@
+use super::smf::Message;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Timbre {
+    Sine,
+    Square,
+    Triangle,
+    Sawtooth,
+}
+
+impl Timbre {
+    // `phase` is in turns, from 0 up to 1.
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Self::Sine => (std::f32::consts::TAU * phase).sin(),
+            Self::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Self::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Self::Sawtooth => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+// Attack, decay and release are in seconds. Sustain is a level between 0 and 1.
+#[derive(Copy, Clone, Debug)]
+pub struct Envelope {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self {
+            attack: 0.01,
+            decay: 0.1,
+            sustain: 0.7,
+            release: 0.2,
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    // Holds the level the note was at when it was released.
+    Release(f32),
+}
+
+struct Voice {
+    channel: u8,
+    key: u8,
+    velocity: f32,
+    // In turns.
+    phase: f32,
+    phase_per_sample: f32,
+    level: f32,
+    stage: Stage,
+}
+
+// General MIDI puts percussion on channel 10, which doesn't make sense as pitched notes.
+const PERCUSSION_CHANNEL: u8 = 9;
+// Notes beyond this many at once steal the oldest voice.
+const MAX_VOICES: usize = 32;
+
+pub(super) struct Synth {
+    sample_rate: f32,
+    pub(super) timbre: Timbre,
+    pub(super) envelope: Envelope,
+    // Scales the sum of all voices. Anything still outside -1..=1 is clipped.
+    pub(super) gain: f32,
+    voices: Vec<Voice>,
+    volumes: [f32; 16],
+    // In semitones.
+    bends: [f32; 16],
+}
+
+impl Synth {
+    pub(super) fn new(sample_rate: usize) -> Self {
+        Self {
+            sample_rate: sample_rate as f32,
+            timbre: Timbre::Sine,
+            envelope: Envelope::default(),
+            gain: 0.25,
+            voices: Vec::new(),
+            // The General MIDI default channel volume.
+            volumes: [100.0 / 127.0; 16],
+            bends: [0.0; 16],
+        }
+    }
+
+    fn phase_per_sample(&self, key: u8, channel: u8) -> f32 {
+        let semitones = key as f32 - 69.0 + self.bends[channel as usize];
+        440.0 * 2f32.powf(semitones / 12.0) / self.sample_rate
+    }
+
+    pub(super) fn handle(&mut self, channel: u8, message: &Message) {
+        match *message {
+            Message::NoteOn { key, velocity } => {
+                if channel == PERCUSSION_CHANNEL {
+                    return;
+                }
+                if self.voices.len() >= MAX_VOICES {
+                    self.voices.remove(0);
+                }
+                self.voices.push(Voice {
+                    channel,
+                    key,
+                    velocity: velocity as f32 / 127.0,
+                    phase: 0.0,
+                    phase_per_sample: self.phase_per_sample(key, channel),
+                    level: 0.0,
+                    stage: Stage::Attack,
+                });
+            }
+            Message::NoteOff { key } => {
+                for voice in &mut self.voices {
+                    if voice.channel == channel && voice.key == key {
+                        voice.release();
+                    }
+                }
+            }
+            Message::AllNotesOff => {
+                for voice in &mut self.voices {
+                    if voice.channel == channel {
+                        voice.release();
+                    }
+                }
+            }
+            Message::Volume(volume) => self.volumes[channel as usize] = volume as f32 / 127.0,
+            Message::PitchBend(bend) => {
+                // The General MIDI default bend range is two semitones either way.
+                self.bends[channel as usize] = bend as f32 / 8192.0 * 2.0;
+                for i in 0..self.voices.len() {
+                    if self.voices[i].channel == channel {
+                        let key = self.voices[i].key;
+                        self.voices[i].phase_per_sample = self.phase_per_sample(key, channel);
+                    }
+                }
+            }
+        }
+    }
+
+    pub(super) fn render_sample(&mut self) -> f32 {
+        let step = 1.0 / self.sample_rate;
+        let envelope = self.envelope;
+
+        let mut sum = 0.0;
+        for voice in &mut self.voices {
+            sum += self.timbre.sample(voice.phase)
+                * voice.level
+                * voice.velocity
+                * self.volumes[voice.channel as usize];
+
+            voice.phase = (voice.phase + voice.phase_per_sample).fract();
+            voice.advance_envelope(&envelope, step);
+        }
+        self.voices
+            .retain(|voice| !matches!(voice.stage, Stage::Release(_)) || voice.level > 0.0);
+
+        (sum * self.gain).clamp(-1.0, 1.0)
+    }
+
+    pub(super) fn is_silent(&self) -> bool {
+        self.voices.is_empty()
+    }
+}
+
+impl Voice {
+    fn release(&mut self) {
+        if !matches!(self.stage, Stage::Release(_)) {
+            self.stage = Stage::Release(self.level);
+        }
+    }
+
+    // Moves the envelope on by `step` seconds, with linear segments between stages.
+    fn advance_envelope(&mut self, envelope: &Envelope, step: f32) {
+        match self.stage {
+            Stage::Attack => {
+                self.level += step / envelope.attack.max(step);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= (1.0 - envelope.sustain) * step / envelope.decay.max(step);
+                if self.level <= envelope.sustain {
+                    self.level = envelope.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => {}
+            Stage::Release(from) => {
+                self.level = (self.level - from * step / envelope.release.max(step)).max(0.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 44100;
+
+    fn render(synth: &mut Synth, seconds: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|_| synth.render_sample())
+            .collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples
+            .iter()
+            .fold(0.0, |peak, sample| sample.abs().max(peak))
+    }
+
+    #[test]
+    fn notes_sound_until_released() {
+        let mut synth = Synth::new(SAMPLE_RATE);
+        synth.handle(0, &Message::Volume(127));
+        synth.handle(
+            0,
+            &Message::NoteOn {
+                key: 69,
+                velocity: 127,
+            },
+        );
+
+        // After the attack and decay, the note holds at the sustain level, at 440 Hz.
+        render(&mut synth, 0.2);
+        let sustained = render(&mut synth, 0.5);
+        let expected = synth.gain * synth.envelope.sustain;
+        assert!((peak(&sustained) - expected).abs() < 0.001);
+        let rising = sustained
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        assert!((219..=221).contains(&rising), "{rising} cycles");
+
+        synth.handle(0, &Message::NoteOff { key: 69 });
+        assert!(!synth.is_silent());
+        let release = synth.envelope.release;
+        let released = render(&mut synth, release + 0.01);
+        assert!(peak(&released[..100]) > 0.9 * expected);
+        assert!(synth.is_silent());
+        assert_eq!(render(&mut synth, 0.1), vec![0.0; SAMPLE_RATE / 10]);
+    }
+
+    #[test]
+    fn percussion_is_left_out() {
+        let mut synth = Synth::new(SAMPLE_RATE);
+        synth.handle(
+            PERCUSSION_CHANNEL,
+            &Message::NoteOn {
+                key: 38,
+                velocity: 127,
+            },
+        );
+        assert!(synth.is_silent());
+    }
+}
@@ -1,20 +1,92 @@
 mod am;
+mod chain;
+mod chirp;
+mod combinator;
+mod dsp;
 mod fm;
+mod integrator;
+mod midi;
+mod multiplexer;
+mod noise;
 mod pcm;
 mod phase;
 mod wave;
 
 pub use am::AmplitudeModulator;
-pub use fm::FrequencyModulator;
+pub use chain::Chain;
+pub use chirp::{Chirp, Sweep};
+pub use combinator::*;
+pub use dsp::{Agc, Biquad, Compressor, Fir, Limiter, Processor};
+pub use fm::{FmCarrier, FrequencyModulator};
+pub use integrator::SignalIntegrator;
+pub use midi::*;
+pub use multiplexer::{Channel, HeadroomReport, Multiplexer};
+pub(crate) use noise::uniform;
+pub use noise::{PinkNoise, WhiteNoise};
 pub use pcm::*;
 pub use wave::*;
 
 use phase::Phase;
+use std::sync::Arc;
+
+// Signals that need room for intermediate values while filling a block work through it this
+// many samples at a time, keeping the room on the stack.
+const CHUNK: usize = 256;
 
 pub trait Signal: Send + Sync {
     fn sample(&self, total_index: u32) -> f32;
+
+    // Fills `samples` with the signal at `start`, `start + 1` and so on.
+    // Signals that can work out a run of samples faster than one at a time override this.
+    fn fill(&self, start: u32, samples: &mut [f32]) {
+        for (total_index, sample) in (start..).zip(samples) {
+            *sample = self.sample(total_index);
+        }
+    }
 }
 
 pub trait IntSignal: Send + Sync {
     fn sample(&self, total_index: u32) -> Phase;
+
+    fn fill(&self, start: u32, samples: &mut [Phase]) {
+        for (total_index, sample) in (start..).zip(samples) {
+            *sample = self.sample(total_index);
+        }
+    }
+}
+
+impl<S: Signal + ?Sized> Signal for Arc<S> {
+    fn sample(&self, total_index: u32) -> f32 {
+        (**self).sample(total_index)
+    }
+    fn fill(&self, start: u32, samples: &mut [f32]) {
+        (**self).fill(start, samples)
+    }
+}
+
+impl<S: Signal + ?Sized> Signal for Box<S> {
+    fn sample(&self, total_index: u32) -> f32 {
+        (**self).sample(total_index)
+    }
+    fn fill(&self, start: u32, samples: &mut [f32]) {
+        (**self).fill(start, samples)
+    }
+}
+
+impl<S: IntSignal + ?Sized> IntSignal for Arc<S> {
+    fn sample(&self, total_index: u32) -> Phase {
+        (**self).sample(total_index)
+    }
+    fn fill(&self, start: u32, samples: &mut [Phase]) {
+        (**self).fill(start, samples)
+    }
+}
+
+impl<S: IntSignal + ?Sized> IntSignal for Box<S> {
+    fn sample(&self, total_index: u32) -> Phase {
+        (**self).sample(total_index)
+    }
+    fn fill(&self, start: u32, samples: &mut [Phase]) {
+        (**self).fill(start, samples)
+    }
 }
@@ -0,0 +1,164 @@
+use super::Signal;
+use std::sync::Arc;
+
+// One program in a Multiplexer, usually a modulator on its own carrier frequency.
+#[derive(Clone)]
+pub struct Channel {
+    pub name: String,
+    pub signal: Arc<dyn Signal>,
+    pub level: f32,
+}
+
+impl Channel {
+    pub fn new(name: &str, signal: Arc<dyn Signal>, level: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            signal,
+            level,
+        }
+    }
+}
+
+// Puts several stations on air at once by adding them together, frequency-division style.
+// The sum is scaled by the total of the levels, so even with every channel at its peak at the
+// same time it stays within -1..=1, which draw_frame maps onto 0..=255.
+#[derive(Clone)]
+pub struct Multiplexer {
+    pub channels: Vec<Channel>,
+}
+
+// Pixels skipped between the samples a HeadroomReport looks at.
+const REPORT_STEP: usize = 7;
+
+pub struct HeadroomReport {
+    // Highest absolute value each channel reached after scaling, in channel order.
+    pub channel_peaks: Vec<f32>,
+    // Highest absolute value of the whole sum, where 1 is full scale.
+    pub peak: f32,
+}
+
+impl HeadroomReport {
+    // Decibels left between the peak and full scale.
+    pub fn headroom_db(&self) -> f32 {
+        -20.0 * self.peak.log10()
+    }
+}
+
+impl Multiplexer {
+    pub fn new(channels: Vec<Channel>) -> Self {
+        Self { channels }
+    }
+
+    fn scale(&self) -> f32 {
+        let total: f32 = self
+            .channels
+            .iter()
+            .map(|channel| channel.level.abs())
+            .sum();
+        if total > 0.0 {
+            1.0 / total
+        } else {
+            0.0
+        }
+    }
+
+    // Measures the peaks of a frame of `frame_size` pixels, looking at every few pixels.
+    // The headroom is what's left for raising the levels of quiet channels, as long as the
+    // stations don't all peak together.
+    pub fn report(&self, frame_size: u32) -> HeadroomReport {
+        let scale = self.scale();
+        let mut channel_peaks = vec![0.0f32; self.channels.len()];
+        let mut peak = 0.0f32;
+        for total_index in (0..frame_size).step_by(REPORT_STEP) {
+            let mut sum = 0.0;
+            for (channel, channel_peak) in self.channels.iter().zip(&mut channel_peaks) {
+                let sample = channel.signal.sample(total_index) * channel.level * scale;
+                *channel_peak = channel_peak.max(sample.abs());
+                sum += sample;
+            }
+            peak = peak.max(sum.abs());
+        }
+
+        HeadroomReport {
+            channel_peaks,
+            peak,
+        }
+    }
+}
+
+impl Signal for Multiplexer {
+    fn sample(&self, total_index: u32) -> f32 {
+        let sum: f32 = self
+            .channels
+            .iter()
+            .map(|channel| channel.signal.sample(total_index) * channel.level)
+            .sum();
+
+        sum * self.scale()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::{Offset, Silence, Square};
+    use crate::{DOT_CLOCK, H_TOTAL, V_TOTAL};
+
+    fn constant(value: f32) -> Arc<dyn Signal> {
+        Arc::new(Offset {
+            signal: Silence,
+            offset: value,
+        })
+    }
+
+    #[test]
+    fn levels_are_scaled_by_their_total() {
+        let multiplexer = Multiplexer::new(vec![
+            Channel::new("a", constant(0.5), 3.0),
+            Channel::new("b", constant(1.0), 1.0),
+        ]);
+        assert_eq!(multiplexer.sample(0), (3.0 * 0.5 + 1.0) / 4.0);
+
+        // A negative level inverts its channel, but still counts towards the total.
+        let multiplexer = Multiplexer::new(vec![
+            Channel::new("a", constant(1.0), 1.0),
+            Channel::new("b", constant(1.0), -3.0),
+        ]);
+        assert_eq!(multiplexer.sample(0), -0.5);
+
+        // Every channel at its peak together is exactly full scale.
+        let multiplexer = Multiplexer::new(vec![
+            Channel::new("a", constant(1.0), 0.2),
+            Channel::new("b", constant(-1.0), -0.3),
+            Channel::new("c", constant(1.0), 0.5),
+        ]);
+        assert!((multiplexer.sample(0) - 1.0).abs() < 1e-6);
+
+        let silent = Multiplexer::new(vec![Channel::new("a", constant(1.0), 0.0)]);
+        assert_eq!(silent.sample(0), 0.0);
+        assert_eq!(Multiplexer::new(vec![]).sample(0), 0.0);
+    }
+
+    #[test]
+    fn report_measures_the_scaled_peaks() {
+        let frame_size = H_TOTAL * V_TOTAL;
+        let multiplexer = Multiplexer::new(vec![
+            Channel::new("a", constant(1.0), 3.0),
+            Channel::new("b", constant(-1.0), 1.0),
+        ]);
+        let report = multiplexer.report(frame_size);
+        assert_eq!(report.channel_peaks, [0.75, 0.25]);
+        assert_eq!(report.peak, 0.5);
+        assert!((report.headroom_db() - 6.0206).abs() < 1e-3);
+
+        // A square wave lines up with a constant for half of every cycle, leaving no headroom.
+        let multiplexer = Multiplexer::new(vec![
+            Channel::new("tone", Arc::new(Square::from_freq(1000, DOT_CLOCK)), 1.0),
+            Channel::new("dc", constant(1.0), 1.0),
+        ]);
+        let report = multiplexer.report(frame_size);
+        assert_eq!(report.channel_peaks, [0.5, 0.5]);
+        assert_eq!(report.peak, 1.0);
+        assert_eq!(report.headroom_db(), 0.0);
+    }
+}
@@ -0,0 +1,103 @@
+use super::Signal;
+
+// Noise is a pure function of the sample index, so that any pixel can be rendered on any thread
+// in any order and still agree with its neighbours.
+fn hash(seed: u64, index: u64) -> u64 {
+    // SplitMix64's finalizer.
+    let mut z = seed ^ index.wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// Uniformly distributed between -1 and 1.
+pub(crate) fn uniform(seed: u64, index: u64) -> f32 {
+    (hash(seed, index) >> 40) as f32 / (1 << 23) as f32 - 1.0
+}
+
+// Counts noise samples, which are held for several pixels when the noise's sample rate is
+// below the dot clock.
+#[derive(Copy, Clone)]
+struct NoiseClock {
+    sample_rate: u64,
+    dot_clock: u64,
+    // Pixels drawn since the noise started.
+    pixel_offset: u64,
+}
+
+impl NoiseClock {
+    fn sample_index(&self, total_index: u32) -> u64 {
+        ((self.pixel_offset + total_index as u64) as u128 * self.sample_rate as u128
+            / self.dot_clock as u128) as u64
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct WhiteNoise {
+    seed: u64,
+    clock: NoiseClock,
+}
+
+impl WhiteNoise {
+    // `sample_rate` sets the bandwidth of the noise, up to the dot clock itself.
+    pub fn new(seed: u64, sample_rate: u32, dot_clock: u32) -> Self {
+        Self {
+            seed,
+            clock: NoiseClock {
+                sample_rate: sample_rate.min(dot_clock) as u64,
+                dot_clock: dot_clock as u64,
+                pixel_offset: 0,
+            },
+        }
+    }
+
+    pub fn next_frame(&mut self, frame_size: u32) {
+        self.clock.pixel_offset += frame_size as u64;
+    }
+}
+
+impl Signal for WhiteNoise {
+    fn sample(&self, total_index: u32) -> f32 {
+        uniform(self.seed, self.clock.sample_index(total_index))
+    }
+}
+
+// Each octave below the noise's sample rate gets its own generator.
+const PINK_OCTAVES: u32 = 16;
+
+// Voss-McCartney pink noise: the sum of white noise generators that each hold their value
+// twice as long as the one before, giving roughly -3 dB per octave.
+#[derive(Copy, Clone)]
+pub struct PinkNoise {
+    seed: u64,
+    clock: NoiseClock,
+}
+
+impl PinkNoise {
+    pub fn new(seed: u64, sample_rate: u32, dot_clock: u32) -> Self {
+        Self {
+            seed,
+            clock: NoiseClock {
+                sample_rate: sample_rate.min(dot_clock) as u64,
+                dot_clock: dot_clock as u64,
+                pixel_offset: 0,
+            },
+        }
+    }
+
+    pub fn next_frame(&mut self, frame_size: u32) {
+        self.clock.pixel_offset += frame_size as u64;
+    }
+}
+
+impl Signal for PinkNoise {
+    fn sample(&self, total_index: u32) -> f32 {
+        let index = self.clock.sample_index(total_index);
+        let sum: f32 = (0..PINK_OCTAVES)
+            .map(|octave| uniform(hash(self.seed, octave as u64), index >> octave))
+            .sum();
+
+        // Scales the sum to an RMS level of about 0.3, clipping the rare peaks.
+        (sum / (2.0 * (PINK_OCTAVES as f32).sqrt())).clamp(-1.0, 1.0)
+    }
+}
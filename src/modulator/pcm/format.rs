@@ -35,3 +35,20 @@ impl PcmFormat for Signed16Le {
             .collect()
     }
 }
+
+// Network byte order, as used by RTP's L16 payload.
+#[derive(Copy, Clone)]
+pub struct Signed16Be(i16);
+
+impl PcmFormat for Signed16Be {
+    const BYTES: usize = mem::size_of::<i16>();
+    fn amplitude(&self) -> f32 {
+        self.0 as f32 / 32768.0
+    }
+    fn from_bytes(bytes: &[u8]) -> Vec<Self> {
+        bytes
+            .chunks_exact(Self::BYTES)
+            .map(|chunk| Self(i16::from_be_bytes(chunk.try_into().unwrap())))
+            .collect()
+    }
+}
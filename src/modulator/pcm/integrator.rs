@@ -1,84 +1,155 @@
-use super::{Interpolation, /*Linear,*/ Nearest, PcmFormat};
-use crate::modulator::phase::Phase;
+use super::{cubic_coefficients, Cubic, Interpolation, Linear, Nearest, PcmFormat, Sinc};
+use crate::modulator::phase::{Phase, PhaseAccumulator};
 use crate::modulator::{IntSignal, Pcm, PcmLoader};
 use std::error::Error;
 use std::num::Wrapping;
 
-struct IntegratedSample<T: PcmFormat> {
-    // The PCM sample itself.
-    sample: T,
-    // The cumulative phase shift of all previous phase shifts.
+struct IntegratedPcm {
+    pcm: Pcm,
+    // The cumulative phase shift at the start of every sample in the frame.
     // Phase is represented by a u32, with 0 being 0° and u32::MAX + 1 being a full 360° turn.
-    cum_phase: Phase,
+    cum_phases: Vec<Phase>,
+    final_phase: PhaseAccumulator,
 }
 
-struct IntegratedPcm<T: PcmFormat> {
-    samples: Vec<IntegratedSample<T>>,
-    sample_rate: usize,
-    pixels_per_sample: f32,
-    final_phase: Phase,
+impl IntegratedPcm {
+    // `sample_integral` gives the integral of the interpolated signal over the whole sample
+    // interval starting at the given sample index, in amplitude × samples.
+    fn new(
+        pcm: Pcm,
+        starting_angle: PhaseAccumulator,
+        sample_integral: impl Fn(&Pcm, isize) -> f32,
+    ) -> Self {
+        let mut phase = starting_angle;
+        let cum_phases = (0..pcm.len as isize)
+            .map(|index| {
+                let cum_phase = phase.phase();
+                phase.add(sample_integral(&pcm, index) as f64 / pcm.sample_rate as f64);
+                cum_phase
+            })
+            .collect();
+
+        Self {
+            pcm,
+            cum_phases,
+            final_phase: phase,
+        }
+    }
+
+    // Index of the sample that `total_index` falls in, and how far into that sample it is.
+    fn position(&self, total_index: u32) -> (isize, f32) {
+        let floating_sample_index = total_index as f32 / self.pcm.pixels_per_sample;
+        let index = (floating_sample_index.floor() as usize).min(self.cum_phases.len() - 1);
+
+        (index as isize, floating_sample_index.fract())
+    }
+
+    // Adds the integral from the start of sample `index` to the cumulative phase at that sample.
+    fn phase(&self, index: isize, partial_integral: f32) -> Phase {
+        self.cum_phases[index as usize]
+            + Phase::from(partial_integral / self.pcm.sample_rate as f32)
+    }
 }
 
 trait Integrable {
     type Interpolation;
 
-    fn integrate(self, starting_angle: Phase) -> Self::Interpolation;
+    fn integrate(self, starting_angle: PhaseAccumulator) -> Self::Interpolation;
 }
 
-impl<T> Integrable for Nearest<Pcm<T>>
-where
-    T: PcmFormat,
-{
-    type Interpolation = Nearest<IntegratedPcm<T>>;
+impl Integrable for Nearest<Pcm> {
+    type Interpolation = Nearest<IntegratedPcm>;
 
-    fn integrate(self, starting_angle: Phase) -> Self::Interpolation {
-        let mut phase = starting_angle;
-        let samples: Vec<IntegratedSample<T>> = self
-            .0
-            .samples
-            .iter()
-            .map(|&sample| {
-                let integrated = IntegratedSample {
-                    sample,
-                    cum_phase: phase,
-                };
-                let phase_per_sample = Phase::from(sample.amplitude() / self.0.sample_rate as f32);
-                phase += phase_per_sample;
-                dbg!(phase.0);
-                integrated
-            })
-            .collect();
-        println!("END FRAME");
+    fn integrate(self, starting_angle: PhaseAccumulator) -> Self::Interpolation {
+        Nearest(IntegratedPcm::new(self.0, starting_angle, |pcm, index| {
+            pcm.amplitude(index)
+        }))
+    }
+}
 
-        Nearest(IntegratedPcm {
-            samples,
-            sample_rate: self.0.sample_rate,
-            pixels_per_sample: self.0.pixels_per_sample,
-            final_phase: phase,
-        })
+impl IntSignal for Nearest<IntegratedPcm> {
+    fn sample(&self, total_index: u32) -> Phase {
+        let (index, t) = self.0.position(total_index);
+
+        self.0.phase(index, self.0.pcm.amplitude(index) * t)
     }
 }
 
-impl<T> IntSignal for Nearest<IntegratedPcm<T>>
-where
-    T: PcmFormat,
-{
+// The integral of a linearly interpolated signal is the trapezoidal rule.
+fn linear_integral(pcm: &Pcm, index: isize, t: f32) -> f32 {
+    let sample = pcm.amplitude(index);
+    let next_sample = pcm.amplitude(index + 1);
+
+    sample * t + (next_sample - sample) * t * t / 2.0
+}
+
+impl Integrable for Linear<Pcm> {
+    type Interpolation = Linear<IntegratedPcm>;
+
+    fn integrate(self, starting_angle: PhaseAccumulator) -> Self::Interpolation {
+        Linear(IntegratedPcm::new(self.0, starting_angle, |pcm, index| {
+            linear_integral(pcm, index, 1.0)
+        }))
+    }
+}
+
+impl IntSignal for Linear<IntegratedPcm> {
     fn sample(&self, total_index: u32) -> Phase {
-        let index = (total_index as f32 / self.0.pixels_per_sample).floor() as usize;
-        let int_sample = &self.0.samples[index];
+        let (index, t) = self.0.position(total_index);
 
-        let current_sample_phase = Phase::from(
-            int_sample.sample.amplitude() / self.0.sample_rate as f32
-                * (total_index as f32 / self.0.pixels_per_sample).fract(),
-        );
+        self.0.phase(index, linear_integral(&self.0.pcm, index, t))
+    }
+}
+
+fn cubic_integral(pcm: &Pcm, index: isize, t: f32) -> f32 {
+    let [c0, c1, c2, c3] = cubic_coefficients(pcm, index);
+
+    (((c3 / 4.0 * t + c2 / 3.0) * t + c1 / 2.0) * t + c0) * t
+}
+
+impl Integrable for Cubic<Pcm> {
+    type Interpolation = Cubic<IntegratedPcm>;
+
+    fn integrate(self, starting_angle: PhaseAccumulator) -> Self::Interpolation {
+        Cubic(IntegratedPcm::new(self.0, starting_angle, |pcm, index| {
+            cubic_integral(pcm, index, 1.0)
+        }))
+    }
+}
+
+impl IntSignal for Cubic<IntegratedPcm> {
+    fn sample(&self, total_index: u32) -> Phase {
+        let (index, t) = self.0.position(total_index);
 
-        int_sample.cum_phase + current_sample_phase
+        self.0.phase(index, cubic_integral(&self.0.pcm, index, t))
+    }
+}
+
+impl Integrable for Sinc<Pcm> {
+    type Interpolation = Sinc<IntegratedPcm>;
+
+    fn integrate(self, starting_angle: PhaseAccumulator) -> Self::Interpolation {
+        let filter = self.filter;
+        let pcm = IntegratedPcm::new(self.pcm, starting_angle, |pcm, index| {
+            filter.integrate(pcm, index, 1.0)
+        });
+
+        Sinc { pcm, filter }
+    }
+}
+
+impl IntSignal for Sinc<IntegratedPcm> {
+    fn sample(&self, total_index: u32) -> Phase {
+        let (index, t) = self.pcm.position(total_index);
+
+        self.pcm
+            .phase(index, self.filter.integrate(&self.pcm.pcm, index, t))
     }
 }
 
 pub struct PreintegratedLoader<T: PcmFormat> {
     internal_loader: PcmLoader<T>,
-    starting_angle: Phase,
+    starting_angle: PhaseAccumulator,
 }
 
 impl<T> PreintegratedLoader<T>
@@ -88,7 +159,7 @@ where
     pub fn new(internal_loader: PcmLoader<T>) -> Self {
         Self {
             internal_loader,
-            starting_angle: Phase(Wrapping(0)),
+            starting_angle: PhaseAccumulator::new(Phase(Wrapping(0))),
         }
     }
 
@@ -98,16 +169,155 @@ where
         Ok(())
     }
 
+    pub fn loader(&self) -> &PcmLoader<T> {
+        &self.internal_loader
+    }
+
+    pub fn loader_mut(&mut self) -> &mut PcmLoader<T> {
+        &mut self.internal_loader
+    }
+
     pub fn samples(&mut self) -> Box<dyn IntSignal> {
-        let pcm = self.internal_loader.pcm();
-
-        //match &self.internal_loader.interpolation {
-        //  Interpolation::Nearest => {
-        let integrated = Nearest(pcm).integrate(self.starting_angle);
-        self.starting_angle = integrated.0.final_phase;
-        Box::new(integrated)
-        //}
-        //Interpolation::Linear => Box::new(Linear(pcm)),
-        //}
+        integrate(
+            self.internal_loader.pcm(),
+            &self.internal_loader.interpolation,
+            &mut self.starting_angle,
+        )
+    }
+}
+
+// Integrates a frame using the given interpolation, continuing on from `starting_angle` and
+// leaving it at the phase reached by the end of the frame.
+pub(in crate::modulator) fn integrate(
+    pcm: Pcm,
+    interpolation: &Interpolation,
+    starting_angle: &mut PhaseAccumulator,
+) -> Box<dyn IntSignal> {
+    match interpolation {
+        Interpolation::Nearest => {
+            let integrated = Nearest(pcm).integrate(*starting_angle);
+            *starting_angle = integrated.0.final_phase;
+            Box::new(integrated)
+        }
+        Interpolation::Linear => {
+            let integrated = Linear(pcm).integrate(*starting_angle);
+            *starting_angle = integrated.0.final_phase;
+            Box::new(integrated)
+        }
+        Interpolation::Cubic => {
+            let integrated = Cubic(pcm).integrate(*starting_angle);
+            *starting_angle = integrated.0.final_phase;
+            Box::new(integrated)
+        }
+        Interpolation::Sinc(filter) => {
+            let integrated = Sinc {
+                pcm,
+                filter: filter.clone(),
+            }
+            .integrate(*starting_angle);
+            *starting_angle = integrated.pcm.final_phase;
+            Box::new(integrated)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::pcm::test_file;
+    use crate::modulator::Signed16Le;
+    use crate::{H_TOTAL, V_TOTAL};
+    use std::f64::consts::TAU;
+
+    const SAMPLE_RATE: usize = 44100;
+    const FREQUENCY: f64 = 1000.0;
+    const AMPLITUDE: f64 = 0.5;
+
+    // Largest difference between the integrated phase and the integral of the sine, over two
+    // frames, as a fraction of the integral's peak.
+    fn integration_error(name: &str, interpolation: Interpolation) -> f64 {
+        // A few frames, which the reader can buffer all of.
+        let samples = (0..SAMPLE_RATE / 10)
+            .map(|i| (AMPLITUDE * (TAU * FREQUENCY * i as f64 / SAMPLE_RATE as f64).sin()) as f32);
+        let path = test_file(name, samples);
+        let mut pcm_loader: PcmLoader<Signed16Le> = PcmLoader::open(&path, SAMPLE_RATE).unwrap();
+        pcm_loader.set_interp(interpolation).unwrap();
+        pcm_loader.wait_for_reader();
+        let mut loader = PreintegratedLoader::new(pcm_loader);
+
+        let frame_size = H_TOTAL * V_TOTAL;
+        let seconds_per_pixel = 1.0 / (frame_size as f64 * crate::VERTICAL_SYNC);
+        let peak = 2.0 * AMPLITUDE / (TAU * FREQUENCY);
+        let mut error: f64 = 0.0;
+        for frame in 0..2 {
+            let integrated = loader.samples();
+            for total_index in (0..frame_size).step_by(997) {
+                let time = (frame * frame_size + total_index) as f64 * seconds_per_pixel;
+                let expected =
+                    AMPLITUDE * (1.0 - (TAU * FREQUENCY * time).cos()) / (TAU * FREQUENCY);
+                let phase = integrated.sample(total_index).0 .0 as i32 as f64 / 2f64.powi(32);
+                error = error.max((phase - expected).abs() / peak);
+            }
+            loader.next_frame().unwrap();
+        }
+
+        std::fs::remove_file(path).unwrap();
+        error
+    }
+
+    #[test]
+    fn nearest_integrates_a_sine() {
+        // Holding every sample for its whole interval delays the signal by half a sample,
+        // which is about 3.6% of the integral's peak at 1 kHz.
+        let error = integration_error("integrate-nearest", Interpolation::Nearest);
+        assert!(error < 0.05, "error {error}");
+    }
+
+    #[test]
+    fn linear_integrates_a_sine() {
+        let error = integration_error("integrate-linear", Interpolation::Linear);
+        assert!(error < 0.003, "error {error}");
+    }
+
+    #[test]
+    fn cubic_integrates_a_sine() {
+        let error = integration_error("integrate-cubic", Interpolation::Cubic);
+        assert!(error < 0.001, "error {error}");
+    }
+
+    #[test]
+    fn sinc_integrates_a_sine() {
+        let error = integration_error("integrate-sinc", Interpolation::sinc(32));
+        assert!(error < 0.001, "error {error}");
+    }
+
+    #[test]
+    fn an_hour_of_a_sine_keeps_to_an_f64_integral() {
+        const LEN: usize = 735;
+        let start = PhaseAccumulator::new(Phase(Wrapping(0x1234_5678)));
+
+        // 997 Hz takes about 44.2 samples a cycle, so frames end all over the cycle, and the
+        // sine's rounding to f32 leaves it not quite zero-mean.
+        let mut phase = start;
+        let mut reference = 0.0f64;
+        for frame in 0..60 * 60 * 60 {
+            let samples: Vec<f32> = (frame * LEN..(frame + 1) * LEN)
+                .map(|n| (0.5 * (TAU * 997.0 * n as f64 / SAMPLE_RATE as f64).sin()) as f32)
+                .collect();
+            reference += samples
+                .iter()
+                .map(|&sample| sample as f64 / SAMPLE_RATE as f64)
+                .sum::<f64>();
+            let pcm = Pcm::new(samples, 0, LEN, SAMPLE_RATE);
+            phase = IntegratedPcm::new(pcm, phase, |pcm, index| pcm.amplitude(index)).final_phase;
+
+            // Only the rounding of the final Phase separates the two.
+            if frame % (60 * 60) == 0 || frame == 60 * 60 * 60 - 1 {
+                let turns = reference - reference.floor();
+                let expected = start.phase().0 + Wrapping((turns * 2f64.powi(32)).round() as u32);
+                let error = (phase.phase().0 - expected).0 as i32;
+                assert!(error.abs() <= 1, "{error} after {frame} frames");
+            }
+        }
     }
 }
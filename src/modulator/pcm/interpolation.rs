@@ -1,37 +1,405 @@
-use super::{Pcm, PcmFormat, Signal};
+use super::{Pcm, Signal};
+use std::sync::Arc;
 
+#[derive(Clone)]
 pub enum Interpolation {
     Nearest,
     Linear,
+    Cubic,
+    Sinc(Arc<SincFilter>),
+}
+
+impl Interpolation {
+    // Band-limited interpolation using a windowed-sinc filter with the given number of taps.
+    pub fn sinc(taps: usize) -> Self {
+        Self::Sinc(Arc::new(SincFilter::new(taps)))
+    }
+
+    pub(crate) fn interpolate(&self, pcm: Pcm) -> Box<dyn Signal> {
+        match self {
+            Self::Nearest => Box::new(Nearest(pcm)),
+            Self::Linear => Box::new(Linear(pcm)),
+            Self::Cubic => Box::new(Cubic(pcm)),
+            Self::Sinc(filter) => Box::new(Sinc {
+                pcm,
+                filter: filter.clone(),
+            }),
+        }
+    }
+
+    // How many samples before and after the current one the method reads.
+    pub(crate) fn margin(&self) -> (usize, usize) {
+        match self {
+            Self::Nearest => (0, 0),
+            Self::Linear => (0, 1),
+            Self::Cubic => (1, 2),
+            Self::Sinc(filter) => (filter.taps / 2 - 1, filter.taps / 2),
+        }
+    }
 }
 
 pub struct Nearest<T>(pub(super) T);
 
-impl<T> Signal for Nearest<Pcm<T>>
-where
-    T: PcmFormat,
-{
+impl Signal for Nearest<Pcm> {
     fn sample(&self, total_index: u32) -> f32 {
-        let sample_index = (total_index as f32 / self.0.pixels_per_sample).floor() as usize;
+        let sample_index = (total_index as f32 / self.0.pixels_per_sample).floor() as isize;
+
+        self.0.amplitude(sample_index)
+    }
 
-        self.0.samples[sample_index].amplitude()
+    fn fill(&self, start: u32, samples: &mut [f32]) {
+        self.0
+            .fill_with(start, samples, |index| self.0.amplitude(index), |c, _| c);
     }
 }
 
 pub struct Linear<T>(pub(super) T);
 
-impl<T> Signal for Linear<Pcm<T>>
-where
-    T: PcmFormat,
-{
+impl Signal for Linear<Pcm> {
     fn sample(&self, total_index: u32) -> f32 {
         let floating_sample_index = total_index as f32 / self.0.pixels_per_sample;
-        let sample_index = floating_sample_index.floor() as usize;
+        let sample_index = floating_sample_index.floor() as isize;
 
         let t = floating_sample_index.fract();
-        let sample = self.0.samples[sample_index].amplitude();
-        let next_sample = self.0.samples[sample_index + 1].amplitude();
+        let sample = self.0.amplitude(sample_index);
+        let next_sample = self.0.amplitude(sample_index + 1);
 
         (1.0 - t) * sample + t * next_sample
     }
+
+    fn fill(&self, start: u32, samples: &mut [f32]) {
+        self.0.fill_with(
+            start,
+            samples,
+            |index| (self.0.amplitude(index), self.0.amplitude(index + 1)),
+            |(sample, next_sample), t| (1.0 - t) * sample + t * next_sample,
+        );
+    }
+}
+
+// Catmull-Rom flavoured cubic Hermite interpolation.
+// Uses one sample before and two samples after the current one.
+pub struct Cubic<T>(pub(super) T);
+
+impl Signal for Cubic<Pcm> {
+    fn sample(&self, total_index: u32) -> f32 {
+        let floating_sample_index = total_index as f32 / self.0.pixels_per_sample;
+        let sample_index = floating_sample_index.floor() as isize;
+        let t = floating_sample_index.fract();
+
+        let [c0, c1, c2, c3] = cubic_coefficients(&self.0, sample_index);
+
+        ((c3 * t + c2) * t + c1) * t + c0
+    }
+
+    fn fill(&self, start: u32, samples: &mut [f32]) {
+        self.0.fill_with(
+            start,
+            samples,
+            |index| cubic_coefficients(&self.0, index),
+            |[c0, c1, c2, c3], t| ((c3 * t + c2) * t + c1) * t + c0,
+        );
+    }
+}
+
+// Polynomial coefficients, lowest power first, of the cubic between `sample_index` and the
+// sample after it.
+pub(super) fn cubic_coefficients(pcm: &Pcm, sample_index: isize) -> [f32; 4] {
+    let previous = pcm.amplitude(sample_index - 1);
+    let current = pcm.amplitude(sample_index);
+    let next = pcm.amplitude(sample_index + 1);
+    let after_next = pcm.amplitude(sample_index + 2);
+
+    let c1 = 0.5 * (next - previous);
+    let c2 = previous - 2.5 * current + 2.0 * next - 0.5 * after_next;
+    let c3 = 0.5 * (after_next - previous) + 1.5 * (current - next);
+
+    [current, c1, c2, c3]
+}
+
+// Number of fractional positions between two samples that get their own row of coefficients.
+// Positions in between two rows are linearly interpolated.
+const SINC_PHASES: usize = 256;
+// Kaiser window shape parameter. 8.0 gives roughly 80 dB of stopband attenuation.
+const KAISER_BETA: f64 = 8.0;
+// Cutoff as a fraction of the sample rate. The transition band is centred on it, so it sits
+// below Nyquist to keep the images just above Nyquist in the stopband.
+const CUTOFF: f64 = 0.45;
+
+// Polyphase table of a Kaiser windowed-sinc low-pass filter with its cutoff at 0.45 times the
+// sample rate. Its transition band is about 5 / taps of the sample rate wide, so with 64 taps
+// it's flat up to about 0.41 times the sample rate, and everything from about 0.49 times up,
+// which takes in every image, is around 80 dB down. Fewer taps widen the transition band.
+pub struct SincFilter {
+    taps: usize,
+    // SINC_PHASES + 1 rows of `taps` coefficients each.
+    // Row p holds the filter for a fractional position of p / SINC_PHASES.
+    coefficients: Vec<f32>,
+    // Same layout as `coefficients`, but row p holds each tap's coefficient integrated over
+    // fractional positions from 0 to p / SINC_PHASES.
+    integrals: Vec<f32>,
+}
+
+impl SincFilter {
+    pub fn new(taps: usize) -> Self {
+        // An even number of taps keeps the filter symmetric around the fractional position.
+        let taps = (taps.max(2) + 1) & !1;
+        let half = (taps / 2) as f64;
+
+        let mut coefficients = Vec::with_capacity((SINC_PHASES + 1) * taps);
+        for phase in 0..=SINC_PHASES {
+            let t = phase as f64 / SINC_PHASES as f64;
+            let row: Vec<f64> = (0..taps)
+                .map(|tap| {
+                    // Distance between the interpolated position and this tap's sample.
+                    let x = t - (tap as f64 - (half - 1.0));
+                    sinc(2.0 * CUTOFF * x) * kaiser(x / half, KAISER_BETA)
+                })
+                .collect();
+
+            // Normalize every row to unity gain at DC so that phases don't ripple.
+            let sum: f64 = row.iter().sum();
+            coefficients.extend(row.iter().map(|&c| (c / sum) as f32));
+        }
+
+        // Trapezoidal running sum of the coefficients down each tap's column.
+        let mut integrals = vec![0.0; coefficients.len()];
+        for phase in 1..=SINC_PHASES {
+            for tap in 0..taps {
+                let i = phase * taps + tap;
+                integrals[i] = integrals[i - taps]
+                    + (coefficients[i - taps] + coefficients[i]) / (2.0 * SINC_PHASES as f32);
+            }
+        }
+
+        Self {
+            taps,
+            coefficients,
+            integrals,
+        }
+    }
+
+    fn row(&self, phase: usize) -> &[f32] {
+        &self.coefficients[phase * self.taps..(phase + 1) * self.taps]
+    }
+
+    fn integral_row(&self, phase: usize) -> &[f32] {
+        &self.integrals[phase * self.taps..(phase + 1) * self.taps]
+    }
+
+    // Runs the filter over the samples around `sample_index` at fractional position `t`.
+    fn apply(&self, pcm: &Pcm, sample_index: isize, t: f32) -> f32 {
+        self.apply_rows(pcm, sample_index, t, Self::row)
+    }
+
+    // Integral of the filtered signal from `sample_index` up to fractional position `t`.
+    pub(super) fn integrate(&self, pcm: &Pcm, sample_index: isize, t: f32) -> f32 {
+        self.apply_rows(pcm, sample_index, t, Self::integral_row)
+    }
+
+    fn apply_rows(
+        &self,
+        pcm: &Pcm,
+        sample_index: isize,
+        t: f32,
+        rows: fn(&Self, usize) -> &[f32],
+    ) -> f32 {
+        let position = t * SINC_PHASES as f32;
+        let phase = (position.floor() as usize).min(SINC_PHASES - 1);
+        let t = position - phase as f32;
+        let row = rows(self, phase);
+        let next_row = rows(self, phase + 1);
+
+        let first_tap = sample_index - (self.taps as isize / 2 - 1);
+        row.iter()
+            .zip(next_row)
+            .enumerate()
+            .map(|(tap, (&c, &next_c))| {
+                let coefficient = c + t * (next_c - c);
+                coefficient * pcm.amplitude(first_tap + tap as isize)
+            })
+            .sum()
+    }
+}
+
+pub(in crate::modulator) fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        return 1.0;
+    }
+    let x = std::f64::consts::PI * x;
+    x.sin() / x
+}
+
+// Kaiser window over x in -1..=1.
+pub(in crate::modulator) fn kaiser(x: f64, beta: f64) -> f64 {
+    if x.abs() > 1.0 {
+        return 0.0;
+    }
+    bessel_i0(beta * (1.0 - x * x).sqrt()) / bessel_i0(beta)
+}
+
+// Zeroth order modified Bessel function of the first kind, evaluated by its power series.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+pub struct Sinc<T> {
+    pub(super) pcm: T,
+    pub(super) filter: Arc<SincFilter>,
+}
+
+impl Signal for Sinc<Pcm> {
+    fn sample(&self, total_index: u32) -> f32 {
+        let floating_sample_index = total_index as f32 / self.pcm.pixels_per_sample;
+        let sample_index = floating_sample_index.floor() as isize;
+
+        self.filter
+            .apply(&self.pcm, sample_index, floating_sample_index.fract())
+    }
+
+    // The filter depends on the fractional position, so only the dynamic dispatch is saved.
+    fn fill(&self, start: u32, samples: &mut [f32]) {
+        self.pcm.fill_with(
+            start,
+            samples,
+            |index| index,
+            |index, t| self.filter.apply(&self.pcm, index, t),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::uniform;
+    use crate::{H_TOTAL, V_TOTAL};
+
+    const SAMPLE_RATE: usize = 44100;
+    // One frame of samples at 60 Hz.
+    const LEN: usize = 735;
+    // A frame length that divides the frame evenly, so that sample points land on pixels.
+    const EVEN_LEN: usize = 940;
+    const PIXELS_PER_SAMPLE: u32 = H_TOTAL * V_TOTAL / EVEN_LEN as u32;
+
+    // A frame of a sine with `cycles` whole cycles in it, with `margin` samples of lookbehind
+    // and lookahead continuing it.
+    fn tone(cycles: usize, margin: usize) -> Pcm {
+        let samples = (0..LEN + 2 * margin)
+            .map(|i| {
+                let t = (i as f64 - margin as f64) / LEN as f64;
+                (std::f64::consts::TAU * cycles as f64 * t).sin() as f32
+            })
+            .collect();
+        Pcm::new(samples, margin, LEN, SAMPLE_RATE)
+    }
+
+    // Amplitude of the component of `samples` with `cycles` whole cycles across them.
+    fn amplitude(samples: &[f32], cycles: usize) -> f64 {
+        let omega = std::f64::consts::TAU * cycles as f64 / samples.len() as f64;
+        let (re, im) = samples
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (i, &sample)| {
+                let angle = omega * i as f64;
+                (
+                    re + sample as f64 * angle.cos(),
+                    im - sample as f64 * angle.sin(),
+                )
+            });
+        2.0 * re.hypot(im) / samples.len() as f64
+    }
+
+    // Interpolates a frame of a tone with `cycles` cycles and returns the amplitude of the
+    // tone and of its first image, in dB.
+    fn tone_and_image_db(interpolation: Interpolation, cycles: usize) -> (f64, f64) {
+        let (margin, _) = interpolation.margin();
+        let signal = interpolation.interpolate(tone(cycles, margin + 1));
+        let mut samples = vec![0.0; (H_TOTAL * V_TOTAL) as usize];
+        signal.fill(0, &mut samples);
+
+        let db = |cycles| 20.0 * amplitude(&samples, cycles).log10();
+        (db(cycles), db(LEN - cycles))
+    }
+
+    #[test]
+    fn sinc_passes_tones_below_its_transition_band() {
+        // 0.4 times the sample rate, which is 17640 Hz.
+        let (tone_db, image_db) = tone_and_image_db(Interpolation::sinc(64), LEN * 2 / 5);
+        assert!(tone_db.abs() < 0.1, "tone at {tone_db} dB");
+        assert!(image_db < -75.0, "image at {image_db} dB");
+    }
+
+    #[test]
+    fn sinc_rejects_images_just_above_nyquist() {
+        // 0.48 times the sample rate, whose first image is at 0.52 times the sample rate.
+        let (_, image_db) = tone_and_image_db(Interpolation::sinc(64), 353);
+        assert!(image_db < -75.0, "image at {image_db} dB");
+    }
+
+    // Frame `frame` of a stream of noise, with the margin Cubic needs taken from the frames on
+    // either side.
+    fn noise_frame(frame: usize) -> Pcm {
+        let (lookbehind, lookahead) = Interpolation::Cubic.margin();
+        let first = frame * EVEN_LEN - lookbehind;
+        let samples = (first..(frame + 1) * EVEN_LEN + lookahead)
+            .map(|n| uniform(7, n as u64))
+            .collect();
+        Pcm::new(samples, lookbehind, EVEN_LEN, SAMPLE_RATE)
+    }
+
+    #[test]
+    fn cubic_passes_through_the_samples() {
+        let signal = Interpolation::Cubic.interpolate(noise_frame(1));
+        let mut samples = vec![0.0; (H_TOTAL * V_TOTAL) as usize];
+        signal.fill(0, &mut samples);
+
+        for n in 0..EVEN_LEN as u32 {
+            let pixel = n * PIXELS_PER_SAMPLE;
+            let expected = uniform(7, EVEN_LEN as u64 + n as u64);
+            assert_eq!(signal.sample(pixel), expected, "sample {n}");
+            assert_eq!(samples[pixel as usize], expected, "sample {n}");
+        }
+    }
+
+    #[test]
+    fn cubic_is_continuous_across_frame_edges() {
+        let frame_size = H_TOTAL * V_TOTAL;
+        for frame in 1..4 {
+            let (this, next) = (noise_frame(frame), noise_frame(frame + 1));
+
+            // The last cubic of one frame ends where the first of the next starts, at the same
+            // slope.
+            let [c0, c1, c2, c3] = cubic_coefficients(&this, EVEN_LEN as isize - 1);
+            let [d0, d1, _, _] = cubic_coefficients(&next, 0);
+            assert!((c0 + c1 + c2 + c3 - d0).abs() < 1e-5, "frame {frame}");
+            assert!(
+                (c1 + 2.0 * c2 + 3.0 * c3 - d1).abs() < 1e-5,
+                "frame {frame}"
+            );
+
+            // So stepping from one frame's last pixel to the next's first is no bigger a step
+            // than any within a frame.
+            let mut samples = vec![0.0; frame_size as usize];
+            let this = Interpolation::Cubic.interpolate(this);
+            this.fill(0, &mut samples);
+            let largest_step = samples
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).abs())
+                .fold(0.0, f32::max);
+            let next = Interpolation::Cubic.interpolate(next);
+            let seam = (next.sample(0) - this.sample(frame_size - 1)).abs();
+            assert!(
+                seam <= largest_step,
+                "frame {frame}: {seam} > {largest_step}"
+            );
+        }
+    }
 }
@@ -1,19 +1,33 @@
 use crate::{DOT_CLOCK, H_TOTAL, VERTICAL_SYNC, V_TOTAL};
 use std::error::Error;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::marker::PhantomData;
 use std::path::Path;
+use std::time::Duration;
 
-use super::{Interpolation, Linear, Nearest, Pcm, PcmFormat, Signal};
+use super::reader::Reader;
+use super::{
+    EofPolicy, Interpolation, LoaderStats, Pcm, PcmFormat, Signal, Source, Track, UnderrunPolicy,
+    Window,
+};
+use crate::modulator::Processor;
+
+// How many frames worth of samples the reader thread may buffer ahead.
+const BUFFERED_FRAMES: usize = 30;
+// How long a new loader waits on the reader thread for the samples it needs before the first
+// frame. Files take next to no time, but a live source may not be sending yet.
+const STARTUP_WAIT: Duration = Duration::from_secs(1);
 
 pub struct PcmLoader<T: PcmFormat> {
-    buffer: BufReader<File>,
+    reader: Reader<T>,
     pub(super) sample_rate: usize,
     pub(super) interpolation: Interpolation,
-    samples_per_frame: usize,
-    pub(super) pixels_per_sample: f32,
-    phantom: PhantomData<T>,
+    // Decoded amplitudes around the current frame.
+    window: Window,
+    underrun_policy: UnderrunPolicy,
+    // The most recently read samples, replayed by UnderrunPolicy::Repeat.
+    last_read: Vec<f32>,
+    // Run over every sample as it enters the window, in the order they were added.
+    processors: Vec<Box<dyn Processor>>,
+    stats: LoaderStats,
 }
 
 impl<T> PcmLoader<T>
@@ -21,59 +35,272 @@ where
     T: PcmFormat + 'static,
 {
     pub fn open<P: AsRef<Path>>(path: P, sample_rate: usize) -> Result<Self, Box<dyn Error>> {
-        let file = File::open(path)?;
+        Self::from_source(Source::File(path.as_ref().to_path_buf()), sample_rate)
+    }
+
+    pub fn from_source(source: Source, sample_rate: usize) -> Result<Self, Box<dyn Error>> {
+        Self::from_playlist(vec![Track::new(source)], sample_rate)
+    }
+
+    // Plays the tracks one after the other, without gaps between them.
+    pub fn from_playlist(tracks: Vec<Track>, sample_rate: usize) -> Result<Self, Box<dyn Error>> {
+        let mut tracks = tracks.into_iter();
+        let first = tracks.next().ok_or("the playlist is empty")?;
+
         dbg!(DOT_CLOCK);
         dbg!(sample_rate);
         let samples_per_frame = (sample_rate as f64 / VERTICAL_SYNC).round() as usize;
         dbg!(samples_per_frame);
         let pixels_per_sample = (H_TOTAL * V_TOTAL) as f32 / samples_per_frame as f32;
         dbg!(pixels_per_sample);
-        let mut buffer = BufReader::with_capacity(T::BYTES * samples_per_frame, file);
-        buffer.fill_buf()?;
+        let start = (first.start.as_secs_f64() * sample_rate as f64).round() as u64;
+        let opened = first.source.open_at(start * T::BYTES as u64)?;
+        let reader = Reader::spawn(
+            first,
+            start,
+            opened,
+            sample_rate,
+            BUFFERED_FRAMES * samples_per_frame,
+            samples_per_frame,
+        );
+        for track in tracks {
+            reader.enqueue(track);
+        }
 
-        Ok(PcmLoader {
-            buffer,
+        let mut loader = PcmLoader {
+            reader,
             sample_rate,
             interpolation: Interpolation::Nearest,
-            pixels_per_sample,
-            samples_per_frame,
-            phantom: PhantomData,
-        })
+            window: Window::new(samples_per_frame),
+            underrun_policy: UnderrunPolicy::Silence,
+            last_read: Vec::new(),
+            processors: Vec::new(),
+            stats: LoaderStats::default(),
+        };
+        loader.fill_window()?;
+
+        Ok(loader)
     }
 
     pub fn next_frame(&mut self) -> Result<(), Box<dyn Error>> {
-        self.buffer.consume(T::BYTES * self.samples_per_frame);
-        loop {
-            self.buffer.fill_buf()?;
-            if self.buffer.buffer().len() == self.buffer.capacity() {
-                break;
-            }
+        self.window.next_frame();
+        self.stats.frames += 1;
+        self.fill_window()?;
+
+        Ok(())
+    }
+
+    // Takes samples from the reader until the window covers the lookbehind, the current frame
+    // and the lookahead, then runs the new samples through the processors.
+    fn fill_window(&mut self) -> Result<(), Box<dyn Error>> {
+        let filled = self.window.samples.len();
+        self.take_samples()?;
+
+        if !self.processors.is_empty() {
+            let added = &mut self.window.samples.make_contiguous()[filled..];
+            self.processors.process(added);
         }
 
         Ok(())
     }
 
-    pub(super) fn pcm(&self) -> Pcm<T> {
-        let bytes = self.buffer.buffer();
-        let samples: Vec<T> = T::from_bytes(bytes);
+    // Once the first frame is under way, never waits on the reader, falling back to the
+    // underrun policy instead.
+    fn take_samples(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(e) = self.reader.take_error() {
+            return Err(e.into());
+        }
+
+        let missing = self.window.missing();
+        if missing == 0 {
+            return Ok(());
+        }
+        let wanted = self.window.wanted();
+
+        // Padding the window while it's still being put together would delay everything after
+        // it, so give the reader a chance to catch up first.
+        if self.stats.frames == 0 {
+            self.reader.wait_for(missing, STARTUP_WAIT);
+        }
+        if self.reader.available() >= missing {
+            self.last_read = self.reader.take(missing);
+            self.window.samples.extend(&self.last_read);
+            return Ok(());
+        }
 
-        Pcm {
-            samples,
-            sample_rate: self.sample_rate,
-            pixels_per_sample: self.pixels_per_sample,
+        // Once the input has ended for good, play out what's left followed by silence.
+        // That isn't an underrun, and neither is a live source that hasn't started sending by
+        // the time the first frame is put together.
+        let policy = if self.reader.has_stopped() || self.stats.frames == 0 {
+            UnderrunPolicy::Silence
+        } else {
+            self.stats.underruns += 1;
+            self.stats.underrun_samples += missing as u64;
+            self.underrun_policy
+        };
+        match policy {
+            UnderrunPolicy::Silence => {
+                let taken = self.reader.take(missing);
+                self.window.samples.extend(&taken);
+                self.window.samples.resize(wanted, 0.0);
+            }
+            UnderrunPolicy::Repeat => {
+                let repeated = self.last_read.iter().cycle().take(missing);
+                self.window.samples.extend(repeated);
+                self.window.samples.resize(wanted, 0.0);
+            }
+            UnderrunPolicy::Pause => self.window.samples.resize(wanted, 0.0),
         }
+
+        Ok(())
+    }
+
+    pub(super) fn pcm(&self) -> Pcm {
+        self.window.pcm(self.sample_rate)
     }
 
     pub fn samples(&self) -> Box<dyn Signal> {
-        let pcm = self.pcm();
+        self.interpolation.interpolate(self.pcm())
+    }
+
+    // Also widens the lookbehind and lookahead if the new method needs more context than
+    // is currently being kept.
+    pub fn set_interp(&mut self, method: Interpolation) -> Result<(), Box<dyn Error>> {
+        self.window.fit(&method);
+        self.interpolation = method;
+        self.fill_window()
+    }
 
-        match &self.interpolation {
-            Interpolation::Nearest => Box::new(Nearest(pcm)),
-            Interpolation::Linear => Box::new(Linear(pcm)),
+    // Sets how many samples before and after the current frame are kept around.
+    // Samples before the very first frame are silent.
+    pub fn set_margin(
+        &mut self,
+        lookbehind: usize,
+        lookahead: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        self.window.set_margin(lookbehind, lookahead);
+        self.fill_window()
+    }
+
+    // Adds a filter or other stage after any already added. It applies from the next samples
+    // read onwards.
+    pub fn add_processor<P: Processor + 'static>(&mut self, processor: P) {
+        self.processors.push(Box::new(processor));
+    }
+
+    pub fn set_underrun_policy(&mut self, policy: UnderrunPolicy) {
+        self.underrun_policy = policy;
+    }
+
+    pub fn set_eof_policy(&mut self, policy: EofPolicy) {
+        self.reader.set_eof_policy(policy);
+    }
+
+    // Continues the track on screen from `position`, measured from its very beginning rather
+    // than its start offset. The frame currently on screen still plays out as it was.
+    // Live sources can't be sought in, and are left playing as they were.
+    pub fn seek(&mut self, position: Duration) -> Result<(), Box<dyn Error>> {
+        let sample = (position.as_secs_f64() * self.sample_rate as f64).round() as u64;
+        // The lookahead was read from the old position.
+        let window = &self.window;
+        let kept = (window.lookbehind + window.samples_per_frame).min(window.samples.len());
+        let discarded = window.samples.len() - kept;
+        let ahead = window.samples.len().saturating_sub(window.lookbehind) as u64;
+        let playing = self.reader.taken().saturating_sub(ahead);
+
+        self.reader.seek(playing, discarded, sample)?;
+        self.window.samples.truncate(kept);
+
+        Ok(())
+    }
+
+    // True once the input has ended and everything read from it has made it into the window.
+    pub fn is_finished(&self) -> bool {
+        self.reader.has_stopped() && self.reader.available() == 0
+    }
+
+    pub fn stats(&self) -> LoaderStats {
+        self.stats
+    }
+
+    // Waits for the reader to get to the end of a short input, so that tests can take frames
+    // faster than real time without underrunning.
+    #[cfg(test)]
+    pub(super) fn wait_for_reader(&self) {
+        self.reader.wait_for(usize::MAX, Duration::from_secs(10));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::pcm::test_file;
+    use crate::modulator::Signed16Le;
+
+    // Sample values that say which sample of which track they came from.
+    fn numbered(len: usize, sign: f32) -> impl Iterator<Item = f32> {
+        (0..len).map(move |i| sign * (i + 1) as f32 / 32767.0)
+    }
+
+    fn frame_start(loader: &PcmLoader<Signed16Le>) -> i32 {
+        let window = &loader.window;
+        (window.samples[window.lookbehind] * 32768.0).round() as i32
+    }
+
+    #[test]
+    fn seek_goes_to_the_track_on_screen() {
+        // Three frames each, so the reader has read all of both, and stopped, while the last
+        // frame of the first is on screen.
+        let first = test_file("seek-first", numbered(3 * 735, 1.0));
+        let second = test_file("seek-second", numbered(3 * 735, -1.0));
+        let tracks = vec![
+            Track::new(first.clone().into()),
+            Track::new(second.clone().into()),
+        ];
+        let mut loader: PcmLoader<Signed16Le> = PcmLoader::from_playlist(tracks, 44100).unwrap();
+        loader.wait_for_reader();
+
+        loader.next_frame().unwrap();
+        loader.next_frame().unwrap();
+        assert_eq!(frame_start(&loader), 2 * 735 + 1);
+        // 100 samples in, counting from 1.
+        loader
+            .seek(Duration::from_secs_f64(99.0 / 44100.0))
+            .unwrap();
+        loader.wait_for_reader();
+
+        loader.next_frame().unwrap();
+        assert_eq!(frame_start(&loader), 100);
+        // The second track follows on again once the first ends, so three frames on, with the
+        // 99 samples skipped in the first, the 100th sample of the second is on screen.
+        for _ in 0..3 {
+            loader.next_frame().unwrap();
         }
+        assert_eq!(frame_start(&loader), -100);
+
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
     }
 
-    pub fn set_interp(&mut self, method: Interpolation) {
-        self.interpolation = method;
+    #[test]
+    fn live_sources_cant_be_sought_in() {
+        let source = Source::Udp("127.0.0.1:0".parse().unwrap());
+        let mut loader: PcmLoader<Signed16Le> = PcmLoader::from_source(source, 44100).unwrap();
+        assert!(loader.seek(Duration::from_secs(10)).is_err());
+    }
+
+    #[test]
+    fn first_frame_is_read_before_the_loader_is_returned() {
+        let path = test_file("first-frame", std::iter::repeat_n(0.5, 44100));
+        let loader: PcmLoader<Signed16Le> = PcmLoader::open(&path, 44100).unwrap();
+
+        assert_eq!(loader.stats().underruns, 0);
+        assert!(loader
+            .window
+            .samples
+            .iter()
+            .take(735)
+            .all(|&sample| sample > 0.49));
+        std::fs::remove_file(path).unwrap();
     }
 }
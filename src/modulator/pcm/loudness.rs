@@ -0,0 +1,200 @@
+use std::error::Error;
+use std::io::{ErrorKind, Read};
+
+use super::{PcmFormat, Source};
+use crate::modulator::{Biquad, Fir, Processor};
+
+// Measurements of a whole track, following EBU R128 / ITU-R BS.1770 for a single channel.
+#[derive(Copy, Clone, Debug)]
+pub struct Loudness {
+    // Gated loudness of the whole track, in LUFS. Negative infinity for silence.
+    pub integrated: f32,
+    // Highest peak between samples as well as on them, in dBTP.
+    pub true_peak: f32,
+}
+
+// Loudness is measured over 400 ms blocks, starting every 100 ms.
+const SEGMENTS_PER_BLOCK: usize = 4;
+const SEGMENTS_PER_SECOND: usize = 10;
+// Blocks quieter than this, in LUFS, are left out entirely.
+const ABSOLUTE_GATE: f64 = -70.0;
+// Blocks more than this many LU below the loudness of the blocks left are left out too.
+const RELATIVE_GATE: f64 = -10.0;
+// True peak is found by upsampling to four times the sample rate.
+const OVERSAMPLING: usize = 4;
+const OVERSAMPLING_TAPS: usize = 49;
+
+// Reads the source all the way through from its beginning, so it has to be rewindable.
+pub fn analyze<T: PcmFormat>(
+    source: &Source,
+    sample_rate: usize,
+) -> Result<Loudness, Box<dyn Error>> {
+    if !source.is_rewindable() {
+        return Err("only sources that can be read again can be analyzed".into());
+    }
+    let mut input = source.open_at(0)?;
+
+    let (mut pre_filter, mut high_pass) = k_weighting(sample_rate);
+    let mut oversampler = Fir::low_pass(
+        sample_rate as f32 / 2.0,
+        OVERSAMPLING_TAPS,
+        OVERSAMPLING * sample_rate,
+    );
+
+    let segment_len = sample_rate / SEGMENTS_PER_SECOND;
+    // Sum of squares of the K-weighted audio over every 100 ms segment.
+    let mut segments = Vec::new();
+    let mut segment = 0.0;
+    let mut segment_filled = 0;
+    let mut peak = 0.0f32;
+
+    let mut bytes = vec![0; T::BYTES * segment_len.max(1)];
+    let mut leftover = 0;
+    loop {
+        let read = match input.read(&mut bytes[leftover..]) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let filled = leftover + read;
+        let whole = filled - filled % T::BYTES;
+        let mut samples: Vec<f32> = T::from_bytes(&bytes[..whole])
+            .iter()
+            .map(PcmFormat::amplitude)
+            .collect();
+        bytes.copy_within(whole..filled, 0);
+        leftover = filled - whole;
+
+        // Zero stuffing followed by a low-pass, which needs making up for the zeros.
+        let mut upsampled = vec![0.0; samples.len() * OVERSAMPLING];
+        for (i, &sample) in samples.iter().enumerate() {
+            upsampled[i * OVERSAMPLING] = sample * OVERSAMPLING as f32;
+        }
+        oversampler.process(&mut upsampled);
+        peak = upsampled
+            .iter()
+            .chain(&samples)
+            .fold(peak, |peak, sample| peak.max(sample.abs()));
+
+        pre_filter.process(&mut samples);
+        high_pass.process(&mut samples);
+        for sample in samples {
+            segment += sample as f64 * sample as f64;
+            segment_filled += 1;
+            if segment_filled == segment_len {
+                segments.push(segment);
+                segment = 0.0;
+                segment_filled = 0;
+            }
+        }
+    }
+
+    let blocks: Vec<f64> = segments
+        .windows(SEGMENTS_PER_BLOCK)
+        .map(|window| window.iter().sum::<f64>() / (SEGMENTS_PER_BLOCK * segment_len) as f64)
+        .filter(|&mean_square| block_loudness(mean_square) > ABSOLUTE_GATE)
+        .collect();
+    let relative_gate = block_loudness(mean(&blocks)) + RELATIVE_GATE;
+    let gated: Vec<f64> = blocks
+        .into_iter()
+        .filter(|&mean_square| block_loudness(mean_square) > relative_gate)
+        .collect();
+
+    Ok(Loudness {
+        integrated: block_loudness(mean(&gated)) as f32,
+        true_peak: 20.0 * peak.log10(),
+    })
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn block_loudness(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+// The two filters of BS.1770's K-weighting: a high shelf modelling the head, then a high-pass.
+// These are the standard's 48 kHz filters redesigned for other sample rates.
+fn k_weighting(sample_rate: usize) -> (Biquad, Biquad) {
+    let sample_rate = sample_rate as f64;
+
+    let (frequency, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (std::f64::consts::PI * frequency / sample_rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let pre_filter = Biquad::new(
+        [
+            vh + vb * k / q + k * k,
+            2.0 * (k * k - vh),
+            vh - vb * k / q + k * k,
+        ],
+        [
+            1.0 + k / q + k * k,
+            2.0 * (k * k - 1.0),
+            1.0 - k / q + k * k,
+        ],
+    );
+
+    let (frequency, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * frequency / sample_rate).tan();
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [
+            1.0 + k / q + k * k,
+            2.0 * (k * k - 1.0),
+            1.0 - k / q + k * k,
+        ],
+    );
+
+    (pre_filter, high_pass)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::pcm::test_file;
+    use crate::modulator::Signed16Le;
+
+    const SAMPLE_RATE: usize = 48000;
+
+    fn analyze_tone(name: &str, tone: impl Fn(f64) -> f64) -> Loudness {
+        let samples = (0..3 * SAMPLE_RATE).map(|i| tone(i as f64 / SAMPLE_RATE as f64) as f32);
+        let path = test_file(name, samples);
+        let loudness = analyze::<Signed16Le>(&Source::File(path.clone()), SAMPLE_RATE).unwrap();
+        std::fs::remove_file(path).unwrap();
+        loudness
+    }
+
+    #[test]
+    fn full_scale_sine_is_minus_3_lufs() {
+        // BS.1770 calibrates K-weighting so that a 1 kHz sine at full scale reads -3.01 LKFS.
+        let loudness = analyze_tone("full-scale-sine", |t| {
+            (std::f64::consts::TAU * 1000.0 * t).sin()
+        });
+        assert!((loudness.integrated + 3.01).abs() < 0.05, "{loudness:?}");
+        assert!(loudness.true_peak.abs() < 0.05, "{loudness:?}");
+    }
+
+    #[test]
+    fn true_peak_is_found_between_samples() {
+        // A sine at a quarter of the sample rate, sampled 45 degrees off its peaks, peaks 3 dB
+        // above its samples. The oversampler's ripple is allowed for.
+        let frequency = SAMPLE_RATE as f64 / 4.0;
+        let phase = std::f64::consts::FRAC_PI_4;
+        let loudness = analyze_tone("between-samples", |t| {
+            0.5 * (std::f64::consts::TAU * frequency * t + phase).sin()
+        });
+        assert!((loudness.true_peak + 6.02).abs() < 0.2, "{loudness:?}");
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        let loudness = analyze_tone("silence", |_| 0.0);
+        assert_eq!(loudness.integrated, f32::NEG_INFINITY);
+    }
+}
@@ -2,17 +2,94 @@ mod format;
 mod integrator;
 mod interpolation;
 mod loader;
+mod loudness;
+mod playlist;
+mod reader;
+mod source;
+mod window;
 
 pub use format::*;
+pub(in crate::modulator) use integrator::integrate;
 pub use integrator::PreintegratedLoader;
 pub use interpolation::*;
 pub use loader::PcmLoader;
+pub use loudness::Loudness;
+pub use playlist::{Repeat, Track};
+pub use reader::{EofPolicy, LoaderStats, UnderrunPolicy};
+pub use source::Source;
+pub(crate) use window::Window;
 
 use super::Signal;
+use crate::{H_TOTAL, V_TOTAL};
 
+// One frame worth of decoded amplitudes, along with the lookbehind and lookahead samples
+// that the loader keeps on either side of it.
 #[derive(Clone)]
-pub struct Pcm<T: PcmFormat> {
-    samples: Vec<T>,
+pub struct Pcm {
+    samples: Vec<f32>,
+    // Position of the frame's first sample within `samples`.
+    start: usize,
+    // Number of samples in the frame itself.
+    len: usize,
     sample_rate: usize,
     pixels_per_sample: f32,
 }
+
+impl Pcm {
+    // `len` samples starting at `start` make up the frame. Any samples around them are
+    // lookbehind and lookahead.
+    pub(crate) fn new(samples: Vec<f32>, start: usize, len: usize, sample_rate: usize) -> Self {
+        Self {
+            samples,
+            start,
+            len,
+            sample_rate,
+            pixels_per_sample: (H_TOTAL * V_TOTAL) as f32 / len as f32,
+        }
+    }
+
+    // Amplitude of the sample at `index`, relative to the start of the frame.
+    // Indices outside of the lookbehind and lookahead hold the first or last sample available.
+    fn amplitude(&self, index: isize) -> f32 {
+        let index = (self.start as isize + index).clamp(0, self.samples.len() as isize - 1);
+        self.samples[index as usize]
+    }
+
+    // Fills a block of pixels. `prepare` works out whatever only depends on which sample a
+    // pixel falls in, once per sample, and `evaluate` finishes each pixel off from that and
+    // how far into the sample the pixel is.
+    fn fill_with<C: Copy>(
+        &self,
+        start: u32,
+        samples: &mut [f32],
+        prepare: impl Fn(isize) -> C,
+        evaluate: impl Fn(C, f32) -> f32,
+    ) {
+        let mut prepared = None;
+        for (total_index, sample) in (start..).zip(samples) {
+            let floating_sample_index = total_index as f32 / self.pixels_per_sample;
+            let sample_index = floating_sample_index.floor() as isize;
+            let c = match prepared {
+                Some((index, c)) if index == sample_index => c,
+                _ => {
+                    let c = prepare(sample_index);
+                    prepared = Some((sample_index, c));
+                    c
+                }
+            };
+            *sample = evaluate(c, floating_sample_index.fract());
+        }
+    }
+}
+
+// Writes `samples` to a file of Signed16Le PCM in the temporary directory, for tests that need
+// a loader to read from. `name` keeps tests running in parallel apart.
+#[cfg(test)]
+pub(super) fn test_file(name: &str, samples: impl Iterator<Item = f32>) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("tempest-crt-{}-{name}.raw", std::process::id()));
+    let bytes: Vec<u8> = samples
+        .flat_map(|sample| ((sample * 32767.0).round() as i16).to_le_bytes())
+        .collect();
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
@@ -0,0 +1,124 @@
+use std::error::Error;
+use std::time::Duration;
+
+use super::{loudness, Loudness, PcmFormat, Source};
+
+// Normalization never lets a track's true peak come out above this, in dBTP.
+const TRUE_PEAK_CEILING: f32 = -1.0;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Repeat {
+    // Play the track this many times in total.
+    Times(u32),
+    Forever,
+}
+
+// An entry in a PcmLoader's playlist.
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub source: Source,
+    // Where every play of the track starts from.
+    pub start: Duration,
+    pub repeat: Repeat,
+    // Applied to every sample as the track is read, in dB.
+    pub gain: f32,
+    // Filled in by normalize, so that the track is only analyzed once.
+    pub loudness: Option<Loudness>,
+}
+
+impl Track {
+    pub fn new(source: Source) -> Self {
+        Self {
+            source,
+            start: Duration::ZERO,
+            repeat: Repeat::Times(1),
+            gain: 0.0,
+            loudness: None,
+        }
+    }
+
+    // Sets the gain so the track plays at `target` LUFS, or as close as it can get without its
+    // true peak going over TRUE_PEAK_CEILING. Reads the whole track the first time.
+    pub fn normalize<T: PcmFormat>(
+        &mut self,
+        target: f32,
+        sample_rate: usize,
+    ) -> Result<Loudness, Box<dyn Error>> {
+        let loudness = match self.loudness {
+            Some(loudness) => loudness,
+            None => loudness::analyze::<T>(&self.source, sample_rate)?,
+        };
+        self.loudness = Some(loudness);
+
+        // Silence has no loudness to speak of, so it's left as it is.
+        self.gain = if loudness.integrated.is_finite() {
+            (target - loudness.integrated).min(TRUE_PEAK_CEILING - loudness.true_peak)
+        } else {
+            0.0
+        };
+
+        Ok(loudness)
+    }
+
+    // The gain as a factor to multiply samples by.
+    pub(super) fn gain_factor(&self) -> f32 {
+        10f32.powf(self.gain / 20.0)
+    }
+
+    // Whether the track should be played again after being played `plays` times.
+    pub(super) fn plays_again(&self, plays: u32) -> bool {
+        let wanted = match self.repeat {
+            Repeat::Times(times) => plays < times,
+            Repeat::Forever => true,
+        };
+        wanted && self.source.is_rewindable()
+    }
+}
+
+impl From<Source> for Track {
+    fn from(source: Source) -> Self {
+        Self::new(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::Signed16Le;
+
+    fn analyzed(integrated: f32, true_peak: f32) -> Track {
+        Track {
+            loudness: Some(Loudness {
+                integrated,
+                true_peak,
+            }),
+            ..Track::new(Source::Stdin)
+        }
+    }
+
+    #[test]
+    fn normalize_reaches_the_target_when_the_peak_allows() {
+        let mut track = analyzed(-18.0, -4.0);
+        track.normalize::<Signed16Le>(-23.0, 44100).unwrap();
+        assert_eq!(track.gain, -5.0);
+
+        let mut track = analyzed(-26.0, -6.0);
+        track.normalize::<Signed16Le>(-23.0, 44100).unwrap();
+        assert_eq!(track.gain, 3.0);
+    }
+
+    #[test]
+    fn normalize_is_held_back_by_the_true_peak() {
+        // Reaching -14 LUFS would take the peak to +2 dBTP, so the peak sets the gain.
+        let mut track = analyzed(-20.0, -4.0);
+        track.normalize::<Signed16Le>(-14.0, 44100).unwrap();
+        assert_eq!(track.gain, TRUE_PEAK_CEILING + 4.0);
+    }
+
+    #[test]
+    fn silence_is_left_alone() {
+        let mut track = analyzed(f32::NEG_INFINITY, f32::NEG_INFINITY);
+        track.normalize::<Signed16Le>(-23.0, 44100).unwrap();
+        assert_eq!(track.gain, 0.0);
+    }
+}
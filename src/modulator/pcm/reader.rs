@@ -0,0 +1,456 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::{ErrorKind, Read};
+use std::marker::PhantomData;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::{PcmFormat, Track};
+
+// What the loader does when the reader thread hasn't delivered enough samples for a frame.
+#[derive(Copy, Clone, Debug)]
+pub enum UnderrunPolicy {
+    // Use whatever has arrived and pad the rest of the frame with silence.
+    Silence,
+    // Leave what has arrived in the buffer and play the most recently read samples again.
+    Repeat,
+    // Leave what has arrived in the buffer and play silence until a full frame is available.
+    Pause,
+}
+
+// What the reader thread does once the current track has been played as many times as it
+// asks for.
+#[derive(Copy, Clone, Debug)]
+pub enum EofPolicy {
+    // Stop reading. The loader plays silence from then on.
+    Stop,
+    // Start the current track again. Sources that can't be rewound stop.
+    Loop,
+    // Move on to the next queued track, stopping if there isn't one.
+    Next,
+    // Move on to the next queued track, sending the finished one to the back of the queue
+    // so that the whole playlist repeats.
+    Cycle,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LoaderStats {
+    pub frames: u64,
+    // Number of frames that couldn't be filled from the buffer.
+    pub underruns: u64,
+    // Number of samples that were made up to cover underruns.
+    pub underrun_samples: u64,
+}
+
+struct State {
+    samples: VecDeque<f32>,
+    capacity: usize,
+    queue: VecDeque<Entry>,
+    // Id for the next track to be queued.
+    next_id: u64,
+    eof_policy: EofPolicy,
+    // Number of samples the loader has taken so far, which is the position in the stream of
+    // the first buffered sample.
+    taken: u64,
+    // Every play of a track that the loader may still be playing, oldest first.
+    plays: VecDeque<Play>,
+    // Where the thread should continue from. Anything it's reading is out of date while set.
+    seek: Option<Seek>,
+    // Set by the reader thread once it has nothing left to read.
+    finished: bool,
+    error: Option<String>,
+    // Set when the loader is dropped so the thread knows to exit.
+    closed: bool,
+}
+
+// A track in the queue. The id tells apart several copies of the same track.
+#[derive(Clone)]
+struct Entry {
+    id: u64,
+    track: Track,
+}
+
+// One play of a track, from when its first sample was read.
+struct Play {
+    // Position of its first sample in the stream.
+    start: u64,
+    entry: Entry,
+    // Number of times the track had already been played.
+    plays: u32,
+    // Whether the track was taken off the front of the queue to start this play.
+    from_queue: bool,
+    // Id of the finished track that EofPolicy::Cycle sent to the back of the queue.
+    cycled: Option<u64>,
+}
+
+struct Seek {
+    entry: Entry,
+    plays: u32,
+    // Sample offset into the track.
+    sample: u64,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // Signalled whenever samples are taken out of a full buffer, or the loader is dropped.
+    space: Condvar,
+    // Signalled whenever samples are added to the buffer, or the thread stops.
+    arrived: Condvar,
+}
+
+// Ring buffer of decoded amplitudes, filled by a background thread reading queued items.
+pub(super) struct Reader<T: PcmFormat> {
+    shared: Arc<Shared>,
+    sample_rate: usize,
+    chunk_size: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T> Reader<T>
+where
+    T: PcmFormat + 'static,
+{
+    // `opened` is `first` after being opened at `start`, which is in samples, so that errors
+    // opening it reach the caller.
+    pub(super) fn spawn(
+        first: Track,
+        start: u64,
+        opened: Box<dyn Read + Send>,
+        sample_rate: usize,
+        capacity: usize,
+        chunk_size: usize,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                samples: VecDeque::with_capacity(capacity),
+                capacity,
+                queue: VecDeque::new(),
+                next_id: 1,
+                eof_policy: EofPolicy::Next,
+                taken: 0,
+                plays: VecDeque::new(),
+                seek: Some(Seek {
+                    entry: Entry {
+                        id: 0,
+                        track: first,
+                    },
+                    plays: 0,
+                    sample: start,
+                }),
+                finished: false,
+                error: None,
+                closed: false,
+            }),
+            space: Condvar::new(),
+            arrived: Condvar::new(),
+        });
+
+        let reader = Self {
+            shared,
+            sample_rate,
+            chunk_size,
+            phantom: PhantomData,
+        };
+        reader.start_thread(Some(opened));
+        reader
+    }
+
+    // Reads from wherever `seek` points, until there's nothing left to read.
+    fn start_thread(&self, opened: Option<Box<dyn Read + Send>>) {
+        let shared = self.shared.clone();
+        let (sample_rate, chunk_size) = (self.sample_rate, self.chunk_size);
+        thread::spawn(move || {
+            let mut opened = opened;
+            loop {
+                let result = read_items::<T>(&shared, opened.take(), sample_rate, chunk_size);
+                let mut state = shared.state.lock().unwrap();
+                match result {
+                    Err(e) => state.error = Some(e.to_string()),
+                    // A seek that came in just as the thread was stopping.
+                    Ok(()) if state.seek.is_some() && !state.closed => continue,
+                    Ok(()) => {}
+                }
+                state.finished = true;
+                shared.arrived.notify_all();
+                return;
+            }
+        });
+    }
+
+    pub(super) fn available(&self) -> usize {
+        self.shared.state.lock().unwrap().samples.len()
+    }
+
+    // Waits until at least `count` samples are buffered, the thread has stopped, or `timeout`
+    // has passed.
+    pub(super) fn wait_for(&self, count: usize, timeout: Duration) {
+        let state = self.shared.state.lock().unwrap();
+        let _ = self
+            .shared
+            .arrived
+            .wait_timeout_while(state, timeout, |state| {
+                state.samples.len() < count && !state.finished
+            })
+            .unwrap();
+    }
+
+    // Takes up to `count` samples without waiting for more to arrive.
+    pub(super) fn take(&self, count: usize) -> Vec<f32> {
+        let mut state = self.shared.state.lock().unwrap();
+        let count = count.min(state.samples.len());
+        let taken = state.samples.drain(..count).collect();
+        state.taken += count as u64;
+
+        // The loader only holds on to a few frames of what it takes, far fewer than the buffer
+        // holds, so plays that ended that long ago can't be sought in any more.
+        let forgotten = state.taken.saturating_sub(state.capacity as u64);
+        while state.plays.len() > 1 && state.plays[1].start <= forgotten {
+            state.plays.pop_front();
+        }
+        self.shared.space.notify_one();
+
+        taken
+    }
+
+    // Number of samples taken so far.
+    pub(super) fn taken(&self) -> u64 {
+        self.shared.state.lock().unwrap().taken
+    }
+
+    // True once the reader thread has stopped. There may still be samples left to take.
+    pub(super) fn has_stopped(&self) -> bool {
+        self.shared.state.lock().unwrap().finished
+    }
+
+    pub(super) fn take_error(&self) -> Option<String> {
+        self.shared.state.lock().unwrap().error.take()
+    }
+
+    pub(super) fn enqueue(&self, track: Track) {
+        let mut state = self.shared.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.queue.push_back(Entry { id, track });
+    }
+
+    // Throws away everything buffered and has the thread carry on from `sample` samples into
+    // the track that was playing at stream position `playing`. `discarded` is how many of the
+    // samples taken so far the loader has thrown away, and will take again from the new
+    // position. Restarts the thread if it had already stopped.
+    pub(super) fn seek(
+        &self,
+        playing: u64,
+        discarded: usize,
+        sample: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut state = self.shared.state.lock().unwrap();
+        let target = state
+            .plays
+            .iter()
+            .rposition(|play| play.start <= playing)
+            .ok_or("nothing is playing")?;
+        if !state.plays[target].entry.track.source.is_rewindable() {
+            return Err("live sources can't be sought in".into());
+        }
+
+        // The thread may have moved on to later tracks already. Put the queue back the way it
+        // was when the track being sought in started.
+        while state.plays.len() > target + 1 {
+            let play = state.plays.pop_back().unwrap();
+            if let Some(id) = play.cycled {
+                state.queue.retain(|entry| entry.id != id);
+            }
+            if play.from_queue {
+                state.queue.push_front(play.entry);
+            }
+        }
+        let play = &state.plays[target];
+        state.seek = Some(Seek {
+            entry: play.entry.clone(),
+            plays: play.plays,
+            sample,
+        });
+        state.samples.clear();
+        state.taken -= discarded as u64;
+        self.shared.space.notify_one();
+
+        if state.finished {
+            state.finished = false;
+            drop(state);
+            self.start_thread(None);
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn set_eof_policy(&self, policy: EofPolicy) {
+        self.shared.state.lock().unwrap().eof_policy = policy;
+    }
+}
+
+impl<T: PcmFormat> Drop for Reader<T> {
+    fn drop(&mut self) {
+        // The thread may be blocked reading a pipe, so it isn't joined. It exits the next
+        // time it goes to push samples.
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.space.notify_one();
+    }
+}
+
+// Byte offset of a point in time within a track.
+fn byte_offset<T: PcmFormat>(time: std::time::Duration, sample_rate: usize) -> u64 {
+    (time.as_secs_f64() * sample_rate as f64).round() as u64 * T::BYTES as u64
+}
+
+// Plays tracks starting from the pending seek. `opened` is the seek's track, already opened.
+fn read_items<T: PcmFormat>(
+    shared: &Shared,
+    mut opened: Option<Box<dyn Read + Send>>,
+    sample_rate: usize,
+    chunk_size: usize,
+) -> std::io::Result<()> {
+    let Some(seek) = shared.state.lock().unwrap().seek.take() else {
+        return Ok(());
+    };
+    let mut entry = seek.entry;
+    let mut plays = seek.plays;
+    let mut offset = seek.sample * T::BYTES as u64;
+    let mut from_queue = false;
+    let mut cycled = None;
+    loop {
+        let input = match opened.take() {
+            Some(input) => input,
+            None => entry.track.source.open_at(offset)?,
+        };
+
+        let mut state = shared.state.lock().unwrap();
+        if let Some(seek) = state.seek.take() {
+            (entry, plays, offset) = (seek.entry, seek.plays, seek.sample * T::BYTES as u64);
+            (from_queue, cycled) = (false, None);
+            continue;
+        }
+        let start = state.taken + state.samples.len() as u64;
+        state.plays.push_back(Play {
+            start,
+            entry: entry.clone(),
+            plays,
+            from_queue,
+            cycled,
+        });
+        drop(state);
+
+        match read_item::<T>(shared, input, entry.track.gain_factor(), chunk_size)? {
+            Outcome::Ended => {}
+            Outcome::Seek => {
+                let seek = shared.state.lock().unwrap().seek.take().unwrap();
+                (entry, plays, offset) = (seek.entry, seek.plays, seek.sample * T::BYTES as u64);
+                (from_queue, cycled) = (false, None);
+                continue;
+            }
+            Outcome::Closed => return Ok(()),
+        }
+
+        // Tracks follow each other in the buffer without any gap, so anything integrating the
+        // loader's output carries its phase straight across.
+        plays += 1;
+        offset = byte_offset::<T>(entry.track.start, sample_rate);
+        (from_queue, cycled) = (false, None);
+        if entry.track.plays_again(plays) {
+            continue;
+        }
+
+        let mut state = shared.state.lock().unwrap();
+        if let Some(seek) = state.seek.take() {
+            (entry, plays, offset) = (seek.entry, seek.plays, seek.sample * T::BYTES as u64);
+            continue;
+        }
+        match state.eof_policy {
+            EofPolicy::Stop => return Ok(()),
+            EofPolicy::Loop if entry.track.source.is_rewindable() => {}
+            EofPolicy::Loop => return Ok(()),
+            EofPolicy::Next | EofPolicy::Cycle => {
+                let Some(next) = state.queue.pop_front() else {
+                    return Ok(());
+                };
+                if let EofPolicy::Cycle = state.eof_policy {
+                    cycled = Some(entry.id);
+                    state.queue.push_back(entry);
+                }
+                entry = next;
+                offset = byte_offset::<T>(entry.track.start, sample_rate);
+                plays = 0;
+                from_queue = true;
+            }
+        }
+    }
+}
+
+enum Outcome {
+    Ended,
+    // The loader asked to continue from somewhere else.
+    Seek,
+    // The loader was dropped.
+    Closed,
+}
+
+// Reads one play of a track until it runs out, pushing its samples into the buffer with the
+// track's gain applied.
+fn read_item<T: PcmFormat>(
+    shared: &Shared,
+    mut file: impl Read,
+    gain: f32,
+    chunk_size: usize,
+) -> std::io::Result<Outcome> {
+    // Stops at the first sign of the loader having moved on.
+    let interrupted = |state: &State| {
+        if state.closed {
+            Some(Outcome::Closed)
+        } else if state.seek.is_some() {
+            Some(Outcome::Seek)
+        } else {
+            None
+        }
+    };
+
+    let mut bytes = vec![0; T::BYTES * chunk_size];
+    // Bytes of a sample that was split between two reads.
+    let mut leftover = 0;
+    loop {
+        if let Some(outcome) = interrupted(&shared.state.lock().unwrap()) {
+            return Ok(outcome);
+        }
+        let read = match file.read(&mut bytes[leftover..]) {
+            Ok(0) => {
+                let state = shared.state.lock().unwrap();
+                return Ok(interrupted(&state).unwrap_or(Outcome::Ended));
+            }
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let filled = leftover + read;
+        let whole = filled - filled % T::BYTES;
+        let samples = T::from_bytes(&bytes[..whole]);
+
+        let mut state = shared.state.lock().unwrap();
+        for sample in samples {
+            loop {
+                if let Some(outcome) = interrupted(&state) {
+                    return Ok(outcome);
+                }
+                if state.samples.len() < state.capacity {
+                    break;
+                }
+                shared.arrived.notify_all();
+                state = shared.space.wait(state).unwrap();
+            }
+            state.samples.push_back(sample.amplitude() * gain);
+        }
+        drop(state);
+        shared.arrived.notify_all();
+
+        bytes.copy_within(whole..filled, 0);
+        leftover = filled - whole;
+    }
+}
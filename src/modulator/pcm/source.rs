@@ -0,0 +1,385 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+// Where a PcmLoader gets its bytes from. Every source yields raw PCM in the loader's format.
+#[derive(Clone, Debug)]
+pub enum Source {
+    File(PathBuf),
+    Stdin,
+    // Raw PCM datagrams, played in the order they arrive.
+    Udp(SocketAddr),
+    // RTP packets, whose payloads are reordered in a jitter buffer holding `jitter_packets`
+    // packets. L16 payloads are big-endian, so pair this with Signed16Be.
+    Rtp {
+        address: SocketAddr,
+        jitter_packets: usize,
+    },
+    // The stdout of a spawned process, such as a decoder writing raw PCM.
+    Command {
+        program: String,
+        args: Vec<String>,
+    },
+}
+
+impl Source {
+    pub fn command<S: AsRef<str>>(program: &str, args: &[S]) -> Self {
+        Self::Command {
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.as_ref().to_string()).collect(),
+        }
+    }
+
+    // Opens the source, skipping its first `offset` bytes. Files seek straight there, anything
+    // else reads and throws away the bytes. For a live source that means skipping input as it
+    // arrives, which is only done for a track's start offset and never to seek.
+    pub(super) fn open_at(&self, offset: u64) -> io::Result<Box<dyn Read + Send>> {
+        if let Self::File(path) = self {
+            let mut file = std::fs::File::open(path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            return Ok(Box::new(file));
+        }
+
+        let mut input = self.open()?;
+        io::copy(&mut input.by_ref().take(offset), &mut io::sink())?;
+        Ok(input)
+    }
+
+    fn open(&self) -> io::Result<Box<dyn Read + Send>> {
+        Ok(match self {
+            Self::File(path) => Box::new(std::fs::File::open(path)?),
+            Self::Stdin => Box::new(io::stdin()),
+            Self::Udp(address) => Box::new(UdpReader {
+                socket: UdpSocket::bind(address)?,
+                datagram: Vec::new(),
+                position: 0,
+            }),
+            Self::Rtp {
+                address,
+                jitter_packets,
+            } => Box::new(RtpReader::new(UdpSocket::bind(address)?, *jitter_packets)),
+            Self::Command { program, args } => {
+                let mut child = Command::new(program)
+                    .args(args)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .spawn()?;
+                let stdout = child.stdout.take().unwrap();
+                Box::new(CommandReader { child, stdout })
+            }
+        })
+    }
+
+    // Whether opening the source again starts it over from the beginning.
+    pub(super) fn is_rewindable(&self) -> bool {
+        matches!(self, Self::File(_) | Self::Command { .. })
+    }
+}
+
+impl From<PathBuf> for Source {
+    fn from(path: PathBuf) -> Self {
+        Self::File(path)
+    }
+}
+
+// Largest datagram we expect, comfortably above the usual Ethernet MTU.
+const MAX_DATAGRAM: usize = 65536;
+
+struct UdpReader {
+    socket: UdpSocket,
+    datagram: Vec<u8>,
+    position: usize,
+}
+
+impl Read for UdpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.datagram.len() {
+            self.datagram.resize(MAX_DATAGRAM, 0);
+            let len = self.socket.recv(&mut self.datagram)?;
+            self.datagram.truncate(len);
+            self.position = 0;
+        }
+
+        let read = buf.len().min(self.datagram.len() - self.position);
+        buf[..read].copy_from_slice(&self.datagram[self.position..self.position + read]);
+        self.position += read;
+
+        Ok(read)
+    }
+}
+
+// Lost packets are covered with silence, but only up to this many at a time, so that a sender
+// restarting with a new sequence number doesn't produce a long gap.
+const MAX_CONCEALED_PACKETS: u16 = 16;
+
+struct RtpReader {
+    socket: UdpSocket,
+    jitter_packets: usize,
+    // Received packets waiting to be played, as sequence number and payload.
+    packets: Vec<(u16, Vec<u8>)>,
+    // Sequence number of the packet that should be played next.
+    next_sequence: Option<u16>,
+    last_payload_len: usize,
+    // The payload currently being read out.
+    payload: Vec<u8>,
+    position: usize,
+}
+
+impl RtpReader {
+    fn new(socket: UdpSocket, jitter_packets: usize) -> Self {
+        Self {
+            socket,
+            jitter_packets: jitter_packets.max(1),
+            packets: Vec::new(),
+            next_sequence: None,
+            last_payload_len: 0,
+            payload: Vec::new(),
+            position: 0,
+        }
+    }
+
+    fn receive(&mut self) -> io::Result<()> {
+        let mut datagram = vec![0; MAX_DATAGRAM];
+        let len = self.socket.recv(&mut datagram)?;
+        datagram.truncate(len);
+
+        let Some((sequence, payload)) = parse_rtp(&datagram) else {
+            return Ok(());
+        };
+        // Packets that show up after we've already moved past them are too late to play.
+        if let Some(next) = self.next_sequence {
+            if (sequence.wrapping_sub(next) as i16) < 0 {
+                return Ok(());
+            }
+        }
+        if self.packets.iter().all(|(s, _)| *s != sequence) {
+            self.packets.push((sequence, payload.to_vec()));
+        }
+
+        Ok(())
+    }
+
+    // Moves the next payload to play out of the jitter buffer, waiting for packets as needed.
+    fn next_payload(&mut self) -> io::Result<()> {
+        loop {
+            if let Some(next) = self.next_sequence {
+                if let Some(i) = self.packets.iter().position(|(s, _)| *s == next) {
+                    let (_, payload) = self.packets.swap_remove(i);
+                    self.next_sequence = Some(next.wrapping_add(1));
+                    self.last_payload_len = payload.len();
+                    self.payload = payload;
+                    self.position = 0;
+                    return Ok(());
+                }
+            }
+
+            if self.packets.len() >= self.jitter_packets {
+                // The packet we're waiting for still hasn't shown up with the buffer full,
+                // so give up on it and skip ahead to the earliest one we have.
+                let reference = self.next_sequence.unwrap_or(self.packets[0].0);
+                let earliest = self
+                    .packets
+                    .iter()
+                    .map(|(s, _)| *s)
+                    .min_by_key(|s| s.wrapping_sub(reference) as i16)
+                    .unwrap();
+
+                if let Some(next) = self.next_sequence {
+                    let lost = earliest.wrapping_sub(next).min(MAX_CONCEALED_PACKETS);
+                    if lost > 0 {
+                        self.payload = vec![0; self.last_payload_len * lost as usize];
+                        self.position = 0;
+                        self.next_sequence = Some(earliest);
+                        return Ok(());
+                    }
+                }
+                self.next_sequence = Some(earliest);
+                continue;
+            }
+
+            self.receive()?;
+        }
+    }
+}
+
+impl Read for RtpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.payload.len() {
+            self.next_payload()?;
+        }
+
+        let read = buf.len().min(self.payload.len() - self.position);
+        buf[..read].copy_from_slice(&self.payload[self.position..self.position + read]);
+        self.position += read;
+
+        Ok(read)
+    }
+}
+
+// Splits an RTP packet into its sequence number and payload, skipping CSRCs, header
+// extensions and padding. Returns None for anything that isn't RTP version 2.
+fn parse_rtp(packet: &[u8]) -> Option<(u16, &[u8])> {
+    const HEADER: usize = 12;
+    if packet.len() < HEADER || packet[0] >> 6 != 2 {
+        return None;
+    }
+    let padding = packet[0] & 0x20 != 0;
+    let extension = packet[0] & 0x10 != 0;
+    let csrc_count = (packet[0] & 0x0f) as usize;
+    let sequence = u16::from_be_bytes([packet[2], packet[3]]);
+
+    let mut start = HEADER + 4 * csrc_count;
+    if extension {
+        let words = packet.get(start + 2..start + 4)?;
+        start += 4 + 4 * u16::from_be_bytes([words[0], words[1]]) as usize;
+    }
+    let mut end = packet.len();
+    if padding {
+        end = end.checked_sub(*packet.last()? as usize)?;
+    }
+
+    Some((sequence, packet.get(start..end)?))
+}
+
+struct CommandReader {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl Read for CommandReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+impl Drop for CommandReader {
+    fn drop(&mut self) {
+        // The process may well have exited on its own already.
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // A version 2 RTP header followed by `csrcs` CSRCs, an extension of `extension` words if
+    // there is one, the payload, and `padding` bytes of padding.
+    fn packet(
+        sequence: u16,
+        csrcs: u8,
+        extension: Option<u16>,
+        payload: &[u8],
+        padding: u8,
+    ) -> Vec<u8> {
+        let mut first = 0x80 | csrcs;
+        if extension.is_some() {
+            first |= 0x10;
+        }
+        if padding > 0 {
+            first |= 0x20;
+        }
+        let mut packet = vec![first, 11];
+        packet.extend(sequence.to_be_bytes());
+        // Timestamp and SSRC.
+        packet.extend([0; 8]);
+        for csrc in 0..csrcs {
+            packet.extend([csrc; 4]);
+        }
+        if let Some(words) = extension {
+            packet.extend([0xbe, 0xde]);
+            packet.extend(words.to_be_bytes());
+            packet.extend(vec![0xee; 4 * words as usize]);
+        }
+        packet.extend(payload);
+        if padding > 0 {
+            packet.extend(vec![0; padding as usize - 1]);
+            packet.push(padding);
+        }
+        packet
+    }
+
+    #[test]
+    fn parses_plain_rtp() {
+        let payload = [1, 2, 3, 4];
+        assert_eq!(
+            parse_rtp(&packet(513, 0, None, &payload, 0)),
+            Some((513, &payload[..]))
+        );
+    }
+
+    #[test]
+    fn skips_csrcs_extensions_and_padding() {
+        let payload = [1, 2, 3, 4, 5, 6];
+        let packet = packet(7, 3, Some(2), &payload, 4);
+        assert_eq!(parse_rtp(&packet), Some((7, &payload[..])));
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        let mut version_1 = packet(7, 0, None, &[1, 2], 0);
+        version_1[0] = 0x40 | (version_1[0] & 0x3f);
+        assert_eq!(parse_rtp(&version_1), None);
+        // Shorter than the fixed header.
+        assert_eq!(parse_rtp(&[0x80, 11, 0, 7]), None);
+        // CSRCs claimed but not there.
+        let mut missing_csrcs = packet(7, 0, None, &[1, 2], 0);
+        missing_csrcs[0] |= 0x04;
+        assert_eq!(parse_rtp(&missing_csrcs), None);
+        // An extension longer than the packet.
+        let mut long_extension = packet(7, 0, Some(1), &[1, 2], 0);
+        long_extension[15] = 9;
+        assert_eq!(parse_rtp(&long_extension), None);
+        // More padding than there is packet.
+        let mut padded = packet(7, 0, None, &[1, 2], 4);
+        *padded.last_mut().unwrap() = 200;
+        assert_eq!(parse_rtp(&padded), None);
+    }
+
+    #[test]
+    fn jitter_buffer_reorders_and_conceals_over_loopback() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let address = receiver.local_addr().unwrap();
+        let mut reader = RtpReader::new(receiver, 4);
+
+        // Every payload is two L16 samples holding its own sequence number.
+        let payload = |sequence: u16| [sequence.to_be_bytes(), sequence.to_be_bytes()].concat();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sent: [u16; 15] = [
+            // Out of order within the jitter buffer.
+            100, 102, 101, 103, 104,
+            // 105 and 106 are missing when the buffer fills up again, and 106 shows up once
+            // it's too late.
+            107, 108, 109, 110, 106, 111,
+            // A jump far enough ahead that only part of it is covered.
+            200, 201, 202, 203,
+        ];
+        for sequence in sent {
+            let packet = packet(sequence, 0, None, &payload(sequence), 0);
+            sender.send_to(&packet, address).unwrap();
+        }
+
+        let mut expected = Vec::new();
+        for sequence in 100..=104 {
+            expected.extend(payload(sequence));
+        }
+        expected.extend([0; 2 * 4]);
+        for sequence in 107..=111 {
+            expected.extend(payload(sequence));
+        }
+        expected.extend([0; MAX_CONCEALED_PACKETS as usize * 4]);
+        for sequence in 200..=203 {
+            expected.extend(payload(sequence));
+        }
+
+        let mut received = vec![0; expected.len()];
+        reader.read_exact(&mut received).unwrap();
+        assert_eq!(received, expected);
+    }
+}
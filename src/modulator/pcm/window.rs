@@ -0,0 +1,71 @@
+use std::collections::VecDeque;
+
+use super::{Interpolation, Pcm};
+
+// Amplitudes starting `lookbehind` samples before the first sample of the current frame and
+// reaching at least `lookahead` samples past its end, kept by whatever hands the interpolators
+// a frame at a time. Whoever owns it tops it up to `wanted` samples after every change.
+pub(crate) struct Window {
+    pub(crate) samples: VecDeque<f32>,
+    pub(crate) lookbehind: usize,
+    pub(crate) lookahead: usize,
+    pub(crate) samples_per_frame: usize,
+}
+
+impl Window {
+    pub(crate) fn new(samples_per_frame: usize) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            lookbehind: 0,
+            lookahead: 0,
+            samples_per_frame,
+        }
+    }
+
+    pub(crate) fn wanted(&self) -> usize {
+        self.lookbehind + self.samples_per_frame + self.lookahead
+    }
+
+    pub(crate) fn missing(&self) -> usize {
+        self.wanted().saturating_sub(self.samples.len())
+    }
+
+    pub(crate) fn next_frame(&mut self) {
+        let consumed = self.samples_per_frame.min(self.samples.len());
+        self.samples.drain(..consumed);
+    }
+
+    // Samples before the very first frame are silent. Samples beyond a shrunken lookahead stay
+    // in the window and are used by later frames.
+    pub(crate) fn set_margin(&mut self, lookbehind: usize, lookahead: usize) {
+        if lookbehind > self.lookbehind {
+            for _ in self.lookbehind..lookbehind {
+                self.samples.push_front(0.0);
+            }
+        } else {
+            self.samples.drain(..self.lookbehind - lookbehind);
+        }
+        self.lookbehind = lookbehind;
+        self.lookahead = lookahead;
+    }
+
+    // Widens the lookbehind and lookahead if `method` needs more context than is currently
+    // being kept.
+    pub(crate) fn fit(&mut self, method: &Interpolation) {
+        let (lookbehind, lookahead) = method.margin();
+        self.set_margin(
+            lookbehind.max(self.lookbehind),
+            lookahead.max(self.lookahead),
+        );
+    }
+
+    pub(crate) fn pcm(&self, sample_rate: usize) -> Pcm {
+        let samples: Vec<f32> = self.samples.iter().take(self.wanted()).copied().collect();
+        Pcm::new(
+            samples,
+            self.lookbehind,
+            self.samples_per_frame,
+            sample_rate,
+        )
+    }
+}
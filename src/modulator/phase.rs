@@ -11,6 +11,11 @@ impl Phase {
     pub(super) fn float(&self) -> f32 {
         self.0 .0 as f32 / (u32::MAX as f32 + 1.0)
     }
+
+    // Same as float, but treats anything past half a turn as negative.
+    pub(super) fn float_signed(&self) -> f32 {
+        self.0 .0 as i32 as f32 / (u32::MAX as f32 + 1.0)
+    }
 }
 
 impl From<f32> for Phase {
@@ -57,3 +62,108 @@ impl AddAssign for Phase {
         *self = *self + rhs
     }
 }
+
+// Running sum of many small phase increments, such as when integrating a signal.
+// Rounding every increment to a Phase would turn the rounding error into a slow random
+// frequency offset, so the sum is kept in 64-bit fixed point where 2^64 is a full turn,
+// leaving 32 bits below the precision of Phase.
+#[derive(Copy, Clone)]
+pub(super) struct PhaseAccumulator(Wrapping<u64>);
+
+impl PhaseAccumulator {
+    pub(super) fn new(phase: Phase) -> Self {
+        Self(Wrapping((phase.0 .0 as u64) << 32))
+    }
+
+    // Adds an increment measured in turns.
+    pub(super) fn add(&mut self, turns: f64) {
+        // Rounding the magnitude before applying the sign makes equal and opposite increments
+        // cancel out exactly.
+        let magnitude = Wrapping((turns.abs().fract() * 2f64.powi(64)).round() as u64);
+        if turns.is_sign_negative() {
+            self.0 -= magnitude;
+        } else {
+            self.0 += magnitude;
+        }
+    }
+
+    // The accumulated phase, rounded to the nearest Phase.
+    pub(super) fn phase(&self) -> Phase {
+        Phase(Wrapping(((self.0 + Wrapping(1 << 31)).0 >> 32) as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Irregular increments of up to 1e-5 turns, some of them negative.
+    fn increment(n: u32) -> f64 {
+        ((n as f64 * 0.618_033_988_749).fract() - 0.3) * 1e-5
+    }
+
+    #[test]
+    fn starts_at_the_phase_it_was_given() {
+        for phase in [0, 1, 0x8000_0000, u32::MAX] {
+            assert_eq!(
+                PhaseAccumulator::new(Phase(Wrapping(phase))).phase().0 .0,
+                phase
+            );
+        }
+    }
+
+    #[test]
+    fn carries_on_across_frames() {
+        const FRAMES: u32 = 100;
+        const LEN: u32 = 1000;
+        let start = Phase(Wrapping(0xdead_beef));
+
+        // One accumulator handed from frame to frame, and one that sees everything at once.
+        let mut carried = PhaseAccumulator::new(start);
+        for frame in 0..FRAMES {
+            let mut accumulator = carried;
+            for n in frame * LEN..(frame + 1) * LEN {
+                accumulator.add(increment(n));
+            }
+            carried = accumulator;
+        }
+        let mut whole = PhaseAccumulator::new(start);
+        (0..FRAMES * LEN).for_each(|n| whole.add(increment(n)));
+        assert_eq!(carried.0, whole.0);
+
+        let turns: f64 = (0..FRAMES * LEN).map(increment).sum();
+        let turns = turns - turns.floor();
+        let expected = start.0 + Wrapping((turns * 2f64.powi(32)).round() as u32);
+        let error = (carried.phase().0 - expected).0 as i32;
+        assert!(error.abs() <= 1, "{error}");
+    }
+
+    #[test]
+    fn equal_and_opposite_increments_cancel() {
+        let mut accumulator = PhaseAccumulator::new(Phase(Wrapping(12345)));
+        let before = accumulator.0;
+        for n in 0..1000 {
+            accumulator.add(increment(n));
+            accumulator.add(-increment(n));
+        }
+        assert_eq!(accumulator.0, before);
+    }
+
+    #[test]
+    fn wraps_around_a_full_turn() {
+        let step = 1.0 / 2f64.powi(32);
+        let phase = |start: u32, turns: &[f64]| {
+            let mut accumulator = PhaseAccumulator::new(Phase(Wrapping(start)));
+            turns.iter().for_each(|&turns| accumulator.add(turns));
+            accumulator.phase().0 .0
+        };
+
+        assert_eq!(phase(u32::MAX - 10, &[20.0 * step]), 9);
+        assert_eq!(phase(5, &[-10.0 * step]), u32::MAX - 4);
+        assert_eq!(phase(0, &[0.75, 0.75]), 0x8000_0000);
+        // Whole turns don't move it, whichever way they go.
+        assert_eq!(phase(1234, &[1.0, -2.0, 3.25, -0.25]), 1234);
+        // Just short of a full turn rounds up to zero.
+        assert_eq!(phase(u32::MAX, &[0.6 * step]), 0);
+    }
+}
@@ -4,16 +4,29 @@ use super::Signal;
 use crate::modulator::IntSignal;
 use std::num::Wrapping;
 
+// The shape of one cycle of a periodic wave. Oscillator turns any shape into a signal that can
+// be used as a carrier or as FM information.
+pub trait Waveform: Send + Sync + Copy {
+    // Value of the wave `phase` of the way through a cycle.
+    fn shape(&self, phase: Phase) -> f32;
+
+    // Integral of the wave from the start of the cycle up to `phase`, measured in cycles.
+    // It has to come back around to 0 by the end of the cycle, so any DC offset is left out.
+    fn integral(&self, phase: Phase) -> f32;
+}
+
 #[derive(Copy, Clone)]
-pub struct Sine {
+pub struct Oscillator<W: Waveform> {
+    waveform: W,
     frequency: u32,
     phase_per_pixel: Phase,
     starting_angle: Phase,
 }
 
-impl Sine {
-    pub fn from_freq(frequency: u32, dot_clock: u32) -> Self {
+impl<W: Waveform> Oscillator<W> {
+    pub fn new(waveform: W, frequency: u32, dot_clock: u32) -> Self {
         Self {
+            waveform,
             frequency,
             phase_per_pixel: Phase::from(frequency as f32 / dot_clock as f32),
             starting_angle: Phase(Wrapping(0)),
@@ -23,65 +36,318 @@ impl Sine {
     pub fn next_frame(&mut self, frame_size: u32) {
         self.starting_angle += self.phase_per_pixel * frame_size;
     }
+
+    fn phase(&self, total_index: u32) -> Phase {
+        self.starting_angle + self.phase_per_pixel * total_index
+    }
+}
+
+impl<W: Waveform + Default> Oscillator<W> {
+    pub fn from_freq(frequency: u32, dot_clock: u32) -> Self {
+        Self::new(W::default(), frequency, dot_clock)
+    }
 }
 
-impl Signal for Sine {
+impl<W: Waveform> Signal for Oscillator<W> {
     fn sample(&self, total_index: u32) -> f32 {
-        let phase = self.starting_angle + self.phase_per_pixel * total_index;
-        (std::f32::consts::TAU * phase.float()).sin()
+        self.waveform.shape(self.phase(total_index))
+    }
+
+    // Adding the phase step pixel by pixel gives exactly the same phases as multiplying.
+    fn fill(&self, start: u32, samples: &mut [f32]) {
+        let mut phase = self.phase(start);
+        for sample in samples {
+            *sample = self.waveform.shape(phase);
+            phase += self.phase_per_pixel;
+        }
     }
 }
 
-impl FmCarrier for Sine {
+impl<W: Waveform> FmCarrier for Oscillator<W> {
     fn sample_with_deviation(&self, total_index: u32, deviation: Phase) -> f32 {
-        let phase = self.starting_angle + self.phase_per_pixel * total_index + deviation;
-        (std::f32::consts::TAU * phase.float()).sin()
+        self.waveform.shape(self.phase(total_index) + deviation)
+    }
+
+    fn fill_with_deviation(&self, start: u32, deviations: &[Phase], samples: &mut [f32]) {
+        let mut phase = self.phase(start);
+        for (sample, &deviation) in samples.iter_mut().zip(deviations) {
+            *sample = self.waveform.shape(phase + deviation);
+            phase += self.phase_per_pixel;
+        }
     }
 }
 
-impl IntSignal for Sine {
+impl<W: Waveform> IntSignal for Oscillator<W> {
     fn sample(&self, total_index: u32) -> Phase {
-        Phase::from(
-            Signal::sample(self, total_index) / self.frequency as f32 / std::f32::consts::TAU,
-        )
+        let integral = self.waveform.integral(self.phase(total_index));
+        Phase::from(integral / self.frequency as f32)
     }
-}
 
-#[derive(Copy, Clone)]
-pub struct Square {
-    phase_per_pixel: Phase,
-    starting_angle: Phase,
+    fn fill(&self, start: u32, samples: &mut [Phase]) {
+        let mut phase = self.phase(start);
+        for sample in samples {
+            *sample = Phase::from(self.waveform.integral(phase) / self.frequency as f32);
+            phase += self.phase_per_pixel;
+        }
+    }
 }
 
-impl Square {
-    pub fn from_freq(frequency: u32, dot_clock: u32) -> Self {
-        Self {
-            phase_per_pixel: Phase::from(frequency as f32 / dot_clock as f32),
-            starting_angle: Phase(Wrapping(0)),
-        }
+#[derive(Copy, Clone, Default)]
+pub struct SineWave;
+
+pub type Sine = Oscillator<SineWave>;
+
+impl Waveform for SineWave {
+    fn shape(&self, phase: Phase) -> f32 {
+        sin(phase)
     }
 
-    pub fn next_frame(&mut self, frame_size: u32) {
-        self.starting_angle += self.phase_per_pixel * frame_size;
+    fn integral(&self, phase: Phase) -> f32 {
+        // A quarter turn ahead of sine is cosine.
+        let cos = sin(phase + Phase(Wrapping(1 << 30)));
+        (1.0 - cos) / std::f32::consts::TAU
     }
 }
 
-impl Signal for Square {
-    fn sample(&self, total_index: u32) -> f32 {
-        let phase = self.starting_angle + self.phase_per_pixel * total_index;
+// sin(2π × phase), several times faster than f32::sin. It's a polynomial with no branches or
+// table lookups, so loops over whole rows of pixels get vectorized: the phase that fill and
+// fill_with_deviation step along is a plain induction variable, and in release builds they
+// come out as packed SSE, four pixels at a time.
+//
+// The phase is folded into the first quarter turn, where a degree 9 Taylor series is never
+// off by more than 4e-6 of full scale. That puts every spur at least 100 dB below the
+// carrier, compared to the roughly 50 dB that 8-bit pixels can manage anyway.
+fn sin(phase: Phase) -> f32 {
+    let turns = phase.float_signed();
+    // Sine is odd, and symmetric around the quarter turn where it peaks.
+    let quarter = 0.25 - (turns.abs() - 0.25).abs();
+
+    let x = std::f32::consts::TAU * quarter;
+    let x2 = x * x;
+    let series = x
+        * (1.0
+            + x2 * (-1.0 / 6.0
+                + x2 * (1.0 / 120.0 + x2 * (-1.0 / 5040.0 + x2 * (1.0 / 362880.0)))));
+
+    series.copysign(turns)
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct SquareWave;
+
+pub type Square = Oscillator<SquareWave>;
+
+impl Waveform for SquareWave {
+    fn shape(&self, phase: Phase) -> f32 {
         if phase.float() < 0.5 {
             return 1.0;
         }
         -1.0
     }
+
+    fn integral(&self, phase: Phase) -> f32 {
+        let phase = phase.float();
+        if phase < 0.5 {
+            return phase;
+        }
+        1.0 - phase
+    }
 }
 
-impl FmCarrier for Square {
-    fn sample_with_deviation(&self, total_index: u32, deviation: Phase) -> f32 {
-        let phase = self.starting_angle + self.phase_per_pixel * total_index + deviation;
-        if phase.float() < 0.5 {
+// Starts at 0 and rises, in step with SineWave.
+#[derive(Copy, Clone, Default)]
+pub struct TriangleWave;
+
+pub type Triangle = Oscillator<TriangleWave>;
+
+impl Waveform for TriangleWave {
+    fn shape(&self, phase: Phase) -> f32 {
+        let phase = phase.float();
+        if phase < 0.25 {
+            4.0 * phase
+        } else if phase < 0.75 {
+            2.0 - 4.0 * phase
+        } else {
+            4.0 * phase - 4.0
+        }
+    }
+
+    fn integral(&self, phase: Phase) -> f32 {
+        let phase = phase.float();
+        if phase < 0.25 {
+            2.0 * phase * phase
+        } else if phase < 0.75 {
+            2.0 * phase - 2.0 * phase * phase - 0.25
+        } else {
+            2.0 * (1.0 - phase) * (1.0 - phase)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct SawtoothWave;
+
+pub type Sawtooth = Oscillator<SawtoothWave>;
+
+impl Waveform for SawtoothWave {
+    fn shape(&self, phase: Phase) -> f32 {
+        2.0 * phase.float() - 1.0
+    }
+
+    fn integral(&self, phase: Phase) -> f32 {
+        let phase = phase.float();
+        phase * phase - phase
+    }
+}
+
+// A rectangular wave that's high for `duty` of every cycle.
+#[derive(Copy, Clone)]
+pub struct PulseWave {
+    pub duty: f32,
+}
+
+pub type Pulse = Oscillator<PulseWave>;
+
+impl Pulse {
+    pub fn with_duty(frequency: u32, duty: f32, dot_clock: u32) -> Self {
+        Self::new(PulseWave { duty }, frequency, dot_clock)
+    }
+}
+
+impl Waveform for PulseWave {
+    fn shape(&self, phase: Phase) -> f32 {
+        if phase.float() < self.duty {
             return 1.0;
         }
         -1.0
     }
+
+    // The wave's average of 2 × duty - 1 is subtracted before integrating.
+    fn integral(&self, phase: Phase) -> f32 {
+        let phase = phase.float();
+        if phase < self.duty {
+            return (2.0 - 2.0 * self.duty) * phase;
+        }
+        2.0 * self.duty * (1.0 - phase)
+    }
+}
+
+// Several sines added together, such as for intermodulation tests.
+#[derive(Clone)]
+pub struct Multitone {
+    tones: Vec<(Sine, f32)>,
+}
+
+impl Multitone {
+    // Takes each tone's frequency and amplitude. If the amplitudes add up to more than 1,
+    // they're scaled down so that the sum can't clip.
+    pub fn from_freqs(tones: &[(u32, f32)], dot_clock: u32) -> Self {
+        let total: f32 = tones.iter().map(|(_, amplitude)| amplitude.abs()).sum();
+        let scale = 1.0 / total.max(1.0);
+
+        Self {
+            tones: tones
+                .iter()
+                .map(|&(frequency, amplitude)| {
+                    (Sine::from_freq(frequency, dot_clock), amplitude * scale)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn next_frame(&mut self, frame_size: u32) {
+        for (tone, _) in &mut self.tones {
+            tone.next_frame(frame_size);
+        }
+    }
+}
+
+impl Signal for Multitone {
+    fn sample(&self, total_index: u32) -> f32 {
+        self.tones
+            .iter()
+            .map(|(tone, amplitude)| Signal::sample(tone, total_index) * amplitude)
+            .sum()
+    }
+}
+
+impl IntSignal for Multitone {
+    fn sample(&self, total_index: u32) -> Phase {
+        self.tones
+            .iter()
+            .fold(Phase(Wrapping(0)), |sum, (tone, amplitude)| {
+                sum + Phase::from(IntSignal::sample(tone, total_index).float_signed() * amplitude)
+            })
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Silence;
+
+impl Signal for Silence {
+    fn sample(&self, _total_index: u32) -> f32 {
+        0.0
+    }
+}
+
+impl IntSignal for Silence {
+    fn sample(&self, _total_index: u32) -> Phase {
+        Phase(Wrapping(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_matches_f32_sin() {
+        // Every quarter turn boundary and its neighbours, then a spread of phases across the
+        // whole turn.
+        let boundaries = (0..4u32).flat_map(|quarter| {
+            let boundary = quarter << 30;
+            [boundary.wrapping_sub(1), boundary, boundary + 1]
+        });
+        let spread = (0..=u32::MAX).step_by(4099);
+
+        for phase in boundaries.chain(spread) {
+            let angle = std::f64::consts::TAU * phase as f64 / (1u64 << 32) as f64;
+            let error = (SineWave.shape(Phase(Wrapping(phase))) - (angle as f32).sin()).abs();
+            assert!(error < 4e-6, "phase {phase:#x} is off by {error}");
+        }
+    }
+
+    // Adds up the wave a little at a time through a cycle, less its average, and checks that
+    // the integral keeps up with it the whole way round.
+    fn check_integral(waveform: impl Waveform, name: &str) {
+        const STEPS: u32 = 4096;
+        let step = 1u32 << (32 - STEPS.trailing_zeros());
+        // Midpoint rule, so that steps touching a jump still count half of each side.
+        let values: Vec<f64> = (0..STEPS)
+            .map(|n| waveform.shape(Phase(Wrapping(n * step + step / 2))) as f64)
+            .collect();
+        let average = values.iter().sum::<f64>() / STEPS as f64;
+
+        let mut integral = 0.0;
+        for (n, value) in (0..STEPS).zip(&values) {
+            let error = integral - waveform.integral(Phase(Wrapping(n * step))) as f64;
+            assert!(error.abs() < 1e-3, "{name} is off by {error} at step {n}");
+            integral += (value - average) / STEPS as f64;
+        }
+        assert!(
+            integral.abs() < 1e-9,
+            "{name} doesn't come back around to 0"
+        );
+    }
+
+    #[test]
+    fn integrals_match_the_waves() {
+        check_integral(SineWave, "sine");
+        check_integral(SquareWave, "square");
+        check_integral(TriangleWave, "triangle");
+        check_integral(SawtoothWave, "sawtooth");
+        // A duty that doesn't land on a step, and one that's mostly high.
+        check_integral(PulseWave { duty: 0.2 }, "pulse 0.2");
+        check_integral(PulseWave { duty: 0.75 }, "pulse 0.75");
+    }
 }
@@ -0,0 +1,265 @@
+mod predistortion;
+mod quantizer;
+mod sigma_delta;
+
+pub use predistortion::Predistortion;
+pub use quantizer::Quantizer;
+pub use sigma_delta::SigmaDelta;
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::modulator::Signal;
+use crate::{visible_to_total_index, H_DISPLAY};
+
+// A frame buffer pointer that can be handed to a worker thread.
+struct FramePtr(*mut u8);
+
+// The pool never lets a frame buffer go out of scope before every job writing to it is done.
+unsafe impl Send for FramePtr {}
+
+// What goes out on each of the three colour channels. Every channel is its own wire pair on
+// VGA or TMDS lane on HDMI, so they can carry separate signals.
+#[derive(Clone)]
+pub enum Channels {
+    // The same signal on red, green and blue.
+    Gray(Arc<dyn Signal>),
+    Rgb([Arc<dyn Signal>; 3]),
+}
+
+struct Job {
+    channels: Channels,
+    // Start of this job's RGBA pixels, and the visible index of its first pixel.
+    pixels: FramePtr,
+    first_pixel: usize,
+    pixel_count: usize,
+    predistortion: Predistortion,
+    quantizer: Quantizer,
+    frame: u64,
+    // Dropped once the job is done, whether it finished or panicked.
+    done: Sender<()>,
+}
+
+// How long frames have been taking to render.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FrameTiming {
+    pub frames: u64,
+    pub last: Duration,
+    // Exponential moving average over roughly the last second of frames.
+    pub average: Duration,
+    pub worst: Duration,
+}
+
+// Threads that stay alive between frames, each rendering its own slice of the frame buffer.
+pub struct RenderPool {
+    jobs: Vec<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    predistortion: Predistortion,
+    quantizer: Quantizer,
+    timing: FrameTiming,
+}
+
+impl RenderPool {
+    pub fn new(threads: usize) -> Self {
+        let (jobs, workers) = (0..threads.max(1))
+            .map(|_| {
+                let (sender, receiver) = mpsc::channel();
+                (sender, thread::spawn(move || work(receiver)))
+            })
+            .unzip();
+
+        Self {
+            jobs,
+            workers,
+            predistortion: Predistortion::None,
+            quantizer: Quantizer::Round,
+            timing: FrameTiming::default(),
+        }
+    }
+
+    // One thread for every core the machine has.
+    pub fn sized_to_machine() -> Self {
+        Self::new(thread::available_parallelism().map_or(1, |threads| threads.get()))
+    }
+
+    pub fn threads(&self) -> usize {
+        self.jobs.len()
+    }
+
+    // Renders the visible pixels of a frame into an RGBA frame buffer in grayscale, and waits
+    // for it.
+    pub fn render(&mut self, modulator: Arc<dyn Signal>, frame: &mut [u8]) {
+        self.render_channels(Channels::Gray(modulator), frame);
+    }
+
+    pub fn render_channels(&mut self, channels: Channels, frame: &mut [u8]) {
+        let started = Instant::now();
+
+        // Quantizers carry state along a row, so jobs are made of whole rows.
+        let pixel_count = frame.len() / 4;
+        let per_thread = pixel_count
+            .div_ceil(self.threads())
+            .next_multiple_of(H_DISPLAY as usize);
+        let (done, finished) = mpsc::channel();
+        let mut sent = 0;
+        let mut worker_exited = false;
+        for (i, (chunk, jobs)) in frame.chunks_mut(per_thread * 4).zip(&self.jobs).enumerate() {
+            let job = Job {
+                channels: channels.clone(),
+                pixels: FramePtr(chunk.as_mut_ptr()),
+                first_pixel: i * per_thread,
+                pixel_count: chunk.len() / 4,
+                predistortion: self.predistortion.clone(),
+                quantizer: self.quantizer.clone(),
+                frame: self.timing.frames,
+                done: done.clone(),
+            };
+            // A job that can't be sent comes back in the error and is dropped with it, but the
+            // ones already sent still have to finish before the frame can be let go of.
+            if jobs.send(job).is_err() {
+                worker_exited = true;
+                break;
+            }
+            sent += 1;
+        }
+        drop(done);
+
+        // Every job's sender has been dropped once this stops receiving, so nothing is still
+        // writing to the frame.
+        let completed = finished.iter().count();
+        assert!(!worker_exited, "render worker exited");
+        assert_eq!(completed, sent, "render worker panicked");
+
+        self.record(started.elapsed());
+    }
+
+    fn record(&mut self, elapsed: Duration) {
+        let timing = &mut self.timing;
+        timing.average = if timing.frames == 0 {
+            elapsed
+        } else {
+            timing.average.mul_f64(59.0 / 60.0) + elapsed.mul_f64(1.0 / 60.0)
+        };
+        timing.frames += 1;
+        timing.last = elapsed;
+        timing.worst = timing.worst.max(elapsed);
+    }
+
+    pub fn set_predistortion(&mut self, predistortion: Predistortion) {
+        self.predistortion = predistortion;
+    }
+
+    pub fn set_quantizer(&mut self, quantizer: Quantizer) {
+        self.quantizer = quantizer;
+    }
+
+    pub fn timing(&self) -> FrameTiming {
+        self.timing
+    }
+}
+
+impl Drop for RenderPool {
+    fn drop(&mut self) {
+        // Closing the job channels lets the workers exit.
+        self.jobs.clear();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work(jobs: Receiver<Job>) {
+    let mut samples = Vec::with_capacity(H_DISPLAY as usize);
+    let mut levels = Vec::with_capacity(H_DISPLAY as usize);
+    for job in jobs {
+        // Safety: the job's pixels are a chunk of the frame passed to render, which doesn't
+        // overlap any other job's and stays borrowed until this job's sender is dropped.
+        let mut pixels =
+            unsafe { std::slice::from_raw_parts_mut(job.pixels.0, job.pixel_count * 4) };
+
+        // Visible pixels within a row have consecutive total indices, so each row's share of
+        // the job is rendered as one block.
+        let mut pixel = job.first_pixel;
+        while !pixels.is_empty() {
+            let row_left = H_DISPLAY as usize - pixel % H_DISPLAY as usize;
+            let (row, rest) = pixels.split_at_mut((row_left * 4).min(pixels.len()));
+
+            let total_index = visible_to_total_index(pixel);
+            samples.resize(row.len() / 4, 0.0);
+            levels.resize(row.len() / 4, 0);
+            match &job.channels {
+                Channels::Gray(signal) => {
+                    render_channel(&job, signal, 0, total_index, &mut samples, &mut levels);
+                    for (rgba, &grayscale) in row.chunks_exact_mut(4).zip(&levels) {
+                        rgba.copy_from_slice(&[grayscale, grayscale, grayscale, 255]);
+                    }
+                }
+                Channels::Rgb(signals) => {
+                    for (channel, signal) in signals.iter().enumerate() {
+                        render_channel(
+                            &job,
+                            signal,
+                            channel,
+                            total_index,
+                            &mut samples,
+                            &mut levels,
+                        );
+                        for (rgba, &level) in row.chunks_exact_mut(4).zip(&levels) {
+                            rgba[channel] = level;
+                            rgba[3] = 255;
+                        }
+                    }
+                }
+            }
+
+            pixel += row.len() / 4;
+            pixels = rest;
+        }
+
+        job.done.send(()).unwrap();
+    }
+}
+
+// Renders one channel of a row into gray levels, going through the predistortion and then
+// the quantizer.
+fn render_channel(
+    job: &Job,
+    signal: &Arc<dyn Signal>,
+    channel: usize,
+    total_index: u32,
+    samples: &mut [f32],
+    levels: &mut [u8],
+) {
+    signal.fill(total_index, samples);
+    job.predistortion.apply(samples);
+    job.quantizer
+        .quantize(samples, total_index, job.frame, channel, levels);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::Sine;
+    use crate::DOT_CLOCK;
+
+    #[test]
+    fn thread_count_does_not_change_the_frame() {
+        // An odd number of rows doesn't split evenly between threads, and error feedback
+        // carries from pixel to pixel, so a job starting mid-row would show.
+        let signal: Arc<dyn Signal> = Arc::new(Sine::from_freq(1_234_567, DOT_CLOCK));
+        let render = |threads| {
+            let mut pool = RenderPool::new(threads);
+            pool.set_quantizer(Quantizer::ErrorFeedback);
+            let mut frame = vec![0; H_DISPLAY as usize * 7 * 4];
+            pool.render(signal.clone(), &mut frame);
+            frame
+        };
+
+        let single = render(1);
+        for threads in [2, 3, 4, 8] {
+            assert!(single == render(threads), "{threads} threads");
+        }
+    }
+}
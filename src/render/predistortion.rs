@@ -0,0 +1,206 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+// Undoes the nonlinearity between pixel values and what actually comes out of the display
+// path, so that a sine drawn to the frame buffer comes out as a sine rather than picking up
+// harmonics. Applied to samples after the modulator and before the quantizer.
+#[derive(Clone, Debug)]
+pub enum Predistortion {
+    None,
+    // Output goes as pixel value to the power of the gamma.
+    Gamma(f32),
+    // The sRGB transfer function, a gamma of roughly 2.2 with a linear part near black.
+    Srgb,
+    // Pixel value to write for each of 256 evenly spaced output levels from black to white,
+    // with values in between interpolated. Useful for a measured DAC.
+    Table(Arc<[f32; 256]>),
+}
+
+impl Predistortion {
+    // Reads a table from a text file of 256 pixel values between 0 and 255, separated by
+    // whitespace or commas.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let values = text
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let table: [f32; 256] = values
+            .try_into()
+            .map_err(|values: Vec<f32>| format!("expected 256 entries, found {}", values.len()))?;
+        if table.iter().any(|value| !(0.0..=255.0).contains(value)) {
+            return Err("table entries must be between 0 and 255".into());
+        }
+
+        Ok(Self::Table(Arc::new(table)))
+    }
+
+    // Works on samples between -1 and 1, as passed on to the quantizer.
+    pub fn apply(&self, samples: &mut [f32]) {
+        if let Self::None = self {
+            return;
+        }
+        for sample in samples {
+            let level = ((*sample + 1.0) / 2.0).clamp(0.0, 1.0);
+            *sample = self.correct(level) * 2.0 - 1.0;
+        }
+    }
+
+    // The pixel value, from 0 to 1, that comes out at `level`.
+    fn correct(&self, level: f32) -> f32 {
+        match self {
+            Self::None => level,
+            Self::Gamma(gamma) => level.powf(1.0 / gamma),
+            Self::Srgb => {
+                if level <= 0.0031308 {
+                    12.92 * level
+                } else {
+                    1.055 * level.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            Self::Table(table) => {
+                let position = level * 255.0;
+                let index = (position.floor() as usize).min(254);
+                let t = position - index as f32;
+                (table[index] + t * (table[index + 1] - table[index])) / 255.0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Evenly spaced samples from -1 to 1.
+    fn ramp() -> Vec<f32> {
+        (0..=100).map(|n| n as f32 / 50.0 - 1.0).collect()
+    }
+
+    fn applied(predistortion: &Predistortion, samples: &[f32]) -> Vec<f32> {
+        let mut samples = samples.to_vec();
+        predistortion.apply(&mut samples);
+        samples
+    }
+
+    // Checks that passing the predistorted samples through the display's own curve, from pixel
+    // value to output level, gets the samples back.
+    fn check_undone_by(predistortion: &Predistortion, display: impl Fn(f32) -> f32) {
+        let samples = ramp();
+        let corrected = applied(predistortion, &samples);
+        for (&sample, &corrected) in samples.iter().zip(&corrected) {
+            let level = display((corrected + 1.0) / 2.0);
+            assert!(
+                (level * 2.0 - 1.0 - sample).abs() < 1e-5,
+                "{predistortion:?} at {sample}"
+            );
+        }
+        // Black and white stay put, and in between the curve only ever rises.
+        assert_eq!(corrected[0], -1.0);
+        assert!((corrected[100] - 1.0).abs() < 1e-6);
+        assert!(corrected.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn none_leaves_samples_alone() {
+        let samples = [-1.5, -1.0, -0.3, 0.0, 0.7, 1.0, 1.5];
+        assert_eq!(applied(&Predistortion::None, &samples), samples);
+    }
+
+    #[test]
+    fn gamma_is_undone_by_the_display() {
+        check_undone_by(&Predistortion::Gamma(2.2), |pixel| pixel.powf(2.2));
+        check_undone_by(&Predistortion::Gamma(0.8), |pixel| pixel.powf(0.8));
+        // A display gamma above 1 darkens the middle, so the pixels have to be brighter.
+        assert!(applied(&Predistortion::Gamma(2.2), &[0.0])[0] > 0.0);
+    }
+
+    #[test]
+    fn srgb_is_undone_by_the_display() {
+        check_undone_by(&Predistortion::Srgb, |pixel| {
+            if pixel <= 0.04045 {
+                pixel / 12.92
+            } else {
+                ((pixel + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        // The linear part near black meets the power curve without a jump.
+        let knee = 0.0031308;
+        let below = Predistortion::Srgb.correct(knee);
+        let above = Predistortion::Srgb.correct(knee + 1e-6);
+        assert!((above - below).abs() < 1e-4);
+    }
+
+    #[test]
+    fn table_interpolates_between_entries() {
+        let identity = Predistortion::Table(Arc::new(std::array::from_fn(|i| i as f32)));
+        let samples = ramp();
+        for (corrected, sample) in applied(&identity, &samples).into_iter().zip(samples) {
+            assert!((corrected - sample).abs() < 1e-5);
+        }
+
+        // Halfway between two levels lands halfway between their pixel values.
+        let mut table = [0.0; 256];
+        table[255] = 255.0;
+        let steep = Predistortion::Table(Arc::new(table));
+        assert_eq!(steep.correct(254.5 / 255.0), 0.5);
+        assert_eq!(steep.correct(0.5), 0.0);
+
+        // Samples out of range are clamped to the ends of the table.
+        assert_eq!(applied(&identity, &[-2.0, 2.0]), [-1.0, 1.0]);
+    }
+
+    fn load(name: &str, text: &str) -> Result<Predistortion, Box<dyn Error>> {
+        let path =
+            std::env::temp_dir().join(format!("tempest-crt-{}-{name}.txt", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let result = Predistortion::load(&path);
+        std::fs::remove_file(path).unwrap();
+        result
+    }
+
+    fn entries(count: usize) -> Vec<String> {
+        (0..count).map(|n| (n % 256).to_string()).collect()
+    }
+
+    #[test]
+    fn load_reads_whitespace_and_commas() {
+        let text = entries(256)
+            .chunks(16)
+            .map(|row| row.join(", "))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let Predistortion::Table(table) = load("table", &text).unwrap() else {
+            panic!("expected a table");
+        };
+        assert!(table
+            .iter()
+            .enumerate()
+            .all(|(i, &value)| value == i as f32));
+    }
+
+    #[test]
+    fn load_rejects_malformed_tables() {
+        let mut out_of_range = entries(256);
+        out_of_range[10] = "255.5".to_string();
+        let mut negative = entries(256);
+        negative[0] = "-1".to_string();
+        let mut not_a_number = entries(256);
+        not_a_number[100] = "bright".to_string();
+
+        for (name, text) in [
+            ("empty", String::new()),
+            ("short", entries(255).join(" ")),
+            ("long", entries(257).join(" ")),
+            ("out-of-range", out_of_range.join(" ")),
+            ("negative", negative.join(" ")),
+            ("not-a-number", not_a_number.join(" ")),
+        ] {
+            assert!(load(name, &text).is_err(), "{name}");
+        }
+        assert!(Predistortion::load("/nonexistent/table.txt").is_err());
+    }
+}
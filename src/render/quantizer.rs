@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use super::SigmaDelta;
+use crate::modulator::uniform;
+use crate::tmds::TmdsQuantizer;
+use crate::DOT_CLOCK;
+
+// How a row of samples between -1 and 1 is turned into 8-bit gray levels.
+// Plain rounding leaves an error that follows the signal, which shows up as spurs in the
+// spectrum. The others trade that for noise, either flat or shaped away from the carrier.
+#[derive(Clone, Debug)]
+pub enum Quantizer {
+    Round,
+    // Adds triangular dither of up to 1 level either way before rounding, turning the spurs
+    // into a flat noise floor.
+    Dither,
+    // Feeds each pixel's rounding error into the next one along the raster, which pushes the
+    // noise up towards half the dot clock.
+    ErrorFeedback,
+    // Second order error feedback that leaves a notch in the noise at `frequency` in Hz,
+    // which is where the carrier should be.
+    NotchedErrorFeedback { frequency: u32 },
+    // Only ever black or white, like the original Tempest for Eliza.
+    SigmaDelta(SigmaDelta),
+    // For HDMI and DVI, where the bytes are sent TMDS encoded. Picks bytes by what their
+    // symbols radiate at the carrier frequency.
+    Tmds(Arc<TmdsQuantizer>),
+}
+
+impl Quantizer {
+    // Quantizes one row, whose first pixel has the given total index. `frame` varies the dither
+    // from frame to frame so that it doesn't form a pattern of its own, and `channel` (0 to 2
+    // for red, green and blue) keeps the colour channels' dither independent.
+    pub fn quantize(
+        &self,
+        samples: &[f32],
+        first_index: u32,
+        frame: u64,
+        channel: usize,
+        levels: &mut [u8],
+    ) {
+        let pixels = levels.iter_mut().zip(samples);
+        match self {
+            Self::Round => {
+                for (level, &sample) in pixels {
+                    *level = to_level(scale(sample));
+                }
+            }
+            Self::Dither => {
+                // Two independent streams for every frame and channel.
+                let seed = 2 * (3 * frame + channel as u64);
+                for (i, (level, &sample)) in pixels.enumerate() {
+                    let index = (first_index + i as u32) as u64;
+                    let dither = (uniform(seed, index) + uniform(seed + 1, index)) / 2.0;
+                    *level = to_level(scale(sample) + dither);
+                }
+            }
+            Self::ErrorFeedback => shape(pixels, [-1.0, 0.0]),
+            Self::NotchedErrorFeedback { frequency } => {
+                let omega = std::f32::consts::TAU * *frequency as f32 / DOT_CLOCK as f32;
+                shape(pixels, [-2.0 * omega.cos(), 1.0])
+            }
+            Self::SigmaDelta(sigma_delta) => sigma_delta.modulate(samples, levels),
+            Self::Tmds(tmds) => tmds.quantize(samples, levels),
+        }
+    }
+}
+
+// From -1..=1 to 0..=255.
+fn scale(sample: f32) -> f32 {
+    sample * (255.0 / 2.0) + 255.0 / 2.0
+}
+
+fn to_level(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+// Error feedback giving the quantization noise a transfer function of
+// 1 + a1 z^-1 + a2 z^-2. The error starts over on every row, since the rows are separated by
+// blanking that isn't drawn.
+fn shape<'a>(pixels: impl Iterator<Item = (&'a mut u8, &'a f32)>, [a1, a2]: [f32; 2]) {
+    let mut errors = [0.0f32; 2];
+    for (level, &sample) in pixels {
+        let wanted = scale(sample) + a1 * errors[0] + a2 * errors[1];
+        *level = to_level(wanted);
+        // Clipping at black or white can leave a large error, which would otherwise keep
+        // feeding back.
+        let error = (*level as f32 - wanted).clamp(-1.0, 1.0);
+        errors = [error, errors[0]];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::H_DISPLAY;
+
+    fn quantize(quantizer: &Quantizer, samples: &[f32]) -> Vec<u8> {
+        let mut levels = vec![0; samples.len()];
+        quantizer.quantize(samples, 0, 0, 0, &mut levels);
+        levels
+    }
+
+    #[test]
+    fn error_feedback_keeps_the_mean_of_a_row() {
+        // 100.3 in levels, which rounding always takes down to 100.
+        let sample = (100.3 - 255.0 / 2.0) / (255.0 / 2.0);
+        let samples = [sample; H_DISPLAY as usize];
+        assert!(quantize(&Quantizer::Round, &samples)
+            .iter()
+            .all(|&level| level == 100));
+
+        let levels = quantize(&Quantizer::ErrorFeedback, &samples);
+        let mean = levels.iter().map(|&level| level as f32).sum::<f32>() / levels.len() as f32;
+        assert!((mean - 100.3).abs() < 0.01, "mean {mean}");
+    }
+
+    #[test]
+    fn notched_error_feedback_leaves_no_noise_at_its_frequency() {
+        let frequency = 10_000_000;
+        let omega = std::f32::consts::TAU * frequency as f32 / DOT_CLOCK as f32;
+        let samples: Vec<f32> = (0..100_000)
+            .map(|n| 0.3 * (0.001 * n as f32).sin() + 0.01 * uniform(3, n))
+            .collect();
+        // The quantization error's component at `frequency`.
+        let noise = |quantizer| {
+            let levels = quantize(&quantizer, &samples);
+            let (re, im) = levels.iter().zip(&samples).enumerate().fold(
+                (0.0, 0.0),
+                |(re, im), (n, (&level, &sample))| {
+                    let error = level as f32 - scale(sample);
+                    let angle = omega * n as f32;
+                    (re + error * angle.cos(), im - error * angle.sin())
+                },
+            );
+            f32::hypot(re, im)
+        };
+
+        let round = noise(Quantizer::Round);
+        let notched = noise(Quantizer::NotchedErrorFeedback { frequency });
+        assert!(notched < round / 20.0, "{notched} against {round}");
+    }
+
+    #[test]
+    fn dither_differs_between_channels() {
+        let samples = [0.1; 256];
+        let channels: Vec<Vec<u8>> = (0..3)
+            .map(|channel| {
+                let mut levels = [0; 256];
+                Quantizer::Dither.quantize(&samples, 1000, 7, channel, &mut levels);
+                levels.to_vec()
+            })
+            .collect();
+
+        assert_ne!(channels[0], channels[1]);
+        assert_ne!(channels[1], channels[2]);
+        assert_ne!(channels[0], channels[2]);
+    }
+}
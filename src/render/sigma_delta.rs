@@ -0,0 +1,160 @@
+use crate::DOT_CLOCK;
+
+// Highest order the modulator supports. Anything above 5 or so is hard to keep stable with
+// a 1-bit quantizer anyway.
+pub const MAX_ORDER: usize = 8;
+
+// Turns samples into pixels that are only ever black or white, with the signal carried by how
+// densely the white pixels are packed. Quantization noise is shaped out of the band around
+// `center`, or away from DC for a plain low-pass modulator, and pushed elsewhere in the
+// spectrum.
+//
+// The noise transfer function has its zeros on the unit circle at the center frequency and
+// poles at the same angles with `pole_radius`. Poles closer to the origin give more noise
+// suppression in band but make the loop less stable, especially at higher orders and with
+// the input near full scale.
+#[derive(Copy, Clone, Debug)]
+pub struct SigmaDelta {
+    // NTF numerator and denominator, lowest power of z^-1 first, with the leading 1 left out.
+    numerator: [f32; MAX_ORDER],
+    denominator: [f32; MAX_ORDER],
+    order: usize,
+}
+
+// If the loop goes unstable anyway, its state grows without bound. Past this it's reset.
+const UNSTABLE: f32 = 64.0;
+
+impl SigmaDelta {
+    // `center` is in Hz. A band-pass modulator's order is rounded up to an even number, as the
+    // zeros come in pairs.
+    pub fn new(order: usize, center: Option<u32>, pole_radius: f32) -> Self {
+        let order = order.clamp(1, MAX_ORDER);
+        let (numerator, denominator) = match center {
+            None => (
+                power(&[1.0, -1.0], order),
+                power(&[1.0, -pole_radius], order),
+            ),
+            Some(center) => {
+                let cos = (std::f32::consts::TAU * center as f32 / DOT_CLOCK as f32).cos();
+                let pairs = order.div_ceil(2).min(MAX_ORDER / 2);
+                (
+                    power(&[1.0, -2.0 * cos, 1.0], pairs),
+                    power(
+                        &[1.0, -2.0 * pole_radius * cos, pole_radius * pole_radius],
+                        pairs,
+                    ),
+                )
+            }
+        };
+
+        let mut sigma_delta = Self {
+            numerator: [0.0; MAX_ORDER],
+            denominator: [0.0; MAX_ORDER],
+            order: numerator.len() - 1,
+        };
+        sigma_delta.numerator[..sigma_delta.order].copy_from_slice(&numerator[1..]);
+        sigma_delta.denominator[..sigma_delta.order].copy_from_slice(&denominator[1..]);
+        sigma_delta
+    }
+
+    // Modulates one row into levels of 0 or 255. Every row starts from a clean state, since
+    // rows are separated by blanking that isn't drawn.
+    pub(super) fn modulate(&self, samples: &[f32], levels: &mut [u8]) {
+        // Past quantization errors, and past differences between output and input, newest
+        // first.
+        let mut errors = [0.0f32; MAX_ORDER];
+        let mut shaped = [0.0f32; MAX_ORDER];
+        let order = self.order;
+
+        for (level, &sample) in levels.iter_mut().zip(samples) {
+            // The part of the shaped noise that's already known from earlier pixels.
+            let feedback: f32 = (0..order)
+                .map(|k| self.numerator[k] * errors[k] - self.denominator[k] * shaped[k])
+                .sum();
+            let wanted = sample.clamp(-1.0, 1.0) + feedback;
+            let output = if wanted >= 0.0 { 1.0 } else { -1.0 };
+            *level = if output > 0.0 { 255 } else { 0 };
+
+            let error = output - wanted;
+            errors.copy_within(..order - 1, 1);
+            shaped.copy_within(..order - 1, 1);
+            errors[0] = error;
+            shaped[0] = error + feedback;
+
+            if feedback.abs() > UNSTABLE {
+                errors = [0.0; MAX_ORDER];
+                shaped = [0.0; MAX_ORDER];
+            }
+        }
+    }
+}
+
+// Multiplies a polynomial by itself `times` times.
+fn power(polynomial: &[f32], times: usize) -> Vec<f32> {
+    let mut result = vec![1.0];
+    for _ in 0..times {
+        let mut product = vec![0.0; result.len() + polynomial.len() - 1];
+        for (i, a) in result.iter().enumerate() {
+            for (j, b) in polynomial.iter().enumerate() {
+                product[i + j] += a * b;
+            }
+        }
+        result = product;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::H_DISPLAY;
+
+    fn modulate(sigma_delta: &SigmaDelta, samples: &[f32]) -> Vec<f32> {
+        let mut levels = vec![0; samples.len()];
+        sigma_delta.modulate(samples, &mut levels);
+        levels
+            .iter()
+            .map(|&level| if level == 255 { 1.0 } else { -1.0 })
+            .collect()
+    }
+
+    #[test]
+    fn low_pass_keeps_the_mean_of_a_row() {
+        let sigma_delta = SigmaDelta::new(2, None, 0.5);
+        for input in [-0.6, -0.2, 0.1, 0.45] {
+            let output = modulate(&sigma_delta, &[input; H_DISPLAY as usize]);
+            let mean = output.iter().sum::<f32>() / output.len() as f32;
+            assert!((mean - input).abs() < 0.01, "{mean} for {input}");
+        }
+    }
+
+    #[test]
+    fn band_pass_keeps_a_tone_at_its_center() {
+        let center = 20_000_000;
+        let omega = std::f32::consts::TAU * center as f32 / DOT_CLOCK as f32;
+        let samples: Vec<f32> = (0..H_DISPLAY)
+            .map(|n| 0.5 * (omega * n as f32).cos())
+            .collect();
+        let output = modulate(&SigmaDelta::new(4, Some(center), 0.5), &samples);
+
+        let (re, im) = output
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (n, &level)| {
+                let angle = omega * n as f32;
+                (re + level * angle.cos(), im - level * angle.sin())
+            });
+        let amplitude = 2.0 * re.hypot(im) / output.len() as f32;
+        assert!((amplitude - 0.5).abs() < 0.02, "amplitude {amplitude}");
+    }
+
+    #[test]
+    fn unstable_loop_is_reset() {
+        // Eighth order with no damping and the input near full scale runs away. Left alone,
+        // the state would grow until the output stuck at black.
+        let output = modulate(&SigmaDelta::new(8, None, 0.0), &[0.9; 100_000]);
+        let tail = &output[90_000..];
+        assert!(tail.contains(&1.0) && tail.contains(&-1.0));
+        assert!(tail.iter().sum::<f32>() > 0.0);
+    }
+}
@@ -0,0 +1,167 @@
+mod quantizer;
+mod spectrum;
+
+pub use quantizer::TmdsQuantizer;
+
+use crate::{visible_to_total_index, DOT_CLOCK, H_DISPLAY, H_TOTAL, V_DISPLAY, V_TOTAL};
+
+// Model of how an HDMI or DVI transmitter sends pixels: every byte of every colour channel
+// becomes a 10-bit TMDS symbol, sent least significant bit first at 10 times the pixel clock.
+// It's those bits that radiate, rather than the gray levels we draw.
+
+pub const BITS_PER_SYMBOL: u32 = 10;
+pub const BIT_RATE: f64 = DOT_CLOCK as f64 * BITS_PER_SYMBOL as f64;
+
+// Symbols sent during blanking instead of pixel data, for each value of the two control bits.
+// Real transmitters also send data islands and guard bands in the blanking on HDMI, which
+// this leaves out, and sync is only carried by channel 0.
+const CONTROL_SYMBOLS: [u16; 4] = [0b1101010100, 0b0010101011, 0b0101010100, 0b1010101011];
+
+// The 8b/10b encoder for one channel, which keeps the running disparity between ones and zeros
+// near 0 to keep the link DC balanced.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TmdsEncoder {
+    // Ones sent minus zeros sent, since the last control period.
+    disparity: i32,
+}
+
+impl TmdsEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn disparity(&self) -> i32 {
+        self.disparity
+    }
+
+    // Encodes a pixel byte following the DVI 1.0 specification.
+    pub fn encode(&mut self, byte: u8) -> u16 {
+        let (word, disparity) = encode_with_disparity(byte, self.disparity);
+        self.disparity = disparity;
+        word
+    }
+
+    // Control periods reset the disparity.
+    pub fn control(&mut self, bits: u8) -> u16 {
+        self.disparity = 0;
+        CONTROL_SYMBOLS[(bits & 0b11) as usize]
+    }
+}
+
+// The symbol for `byte` when the running disparity is `disparity`, and the disparity after it.
+pub(crate) fn encode_with_disparity(byte: u8, disparity: i32) -> (u16, i32) {
+    // First stage: transition minimization, by XORing or XNORing each bit with the last.
+    let ones = byte.count_ones();
+    let use_xnor = ones > 4 || (ones == 4 && byte & 1 == 0);
+    let mut q_m = (byte & 1) as u16;
+    for i in 1..8 {
+        let previous = (q_m >> (i - 1)) & 1;
+        let bit = ((byte >> i) & 1) as u16;
+        let next = if use_xnor {
+            !(previous ^ bit) & 1
+        } else {
+            previous ^ bit
+        };
+        q_m |= next << i;
+    }
+    if !use_xnor {
+        q_m |= 1 << 8;
+    }
+
+    // Second stage: DC balancing, by inverting the data bits when that brings the
+    // disparity back towards 0.
+    let data = q_m & 0xff;
+    let xor_used = q_m >> 8 == 1;
+    let ones = data.count_ones() as i32;
+    let zeros = 8 - ones;
+
+    if disparity == 0 || ones == zeros {
+        if xor_used {
+            (q_m, disparity + ones - zeros)
+        } else {
+            (1 << 9 | (!data & 0xff), disparity + zeros - ones)
+        }
+    } else if (disparity > 0 && ones > zeros) || (disparity < 0 && zeros > ones) {
+        let word = 1 << 9 | (q_m & 1 << 8) | (!data & 0xff);
+        (word, disparity + 2 * xor_used as i32 + zeros - ones)
+    } else {
+        (q_m, disparity - 2 * !xor_used as i32 + ones - zeros)
+    }
+}
+
+// Every TMDS symbol of one colour channel over a whole frame, blanking included.
+pub struct TmdsStream {
+    pub symbols: Vec<u16>,
+}
+
+impl TmdsStream {
+    // `frame` is RGBA, as drawn by the render pool, and `channel` picks red, green or blue.
+    // TMDS channel 0 carries blue, 1 green and 2 red, but that only matters for sync.
+    pub fn from_frame(frame: &[u8], channel: usize) -> Self {
+        let mut symbols = vec![0; (H_TOTAL * V_TOTAL) as usize];
+        let mut encoder = TmdsEncoder::new();
+        let mut next_index = 0;
+        for pixel in 0..(H_DISPLAY * V_DISPLAY) as usize {
+            let total_index = visible_to_total_index(pixel) as usize;
+            for symbol in &mut symbols[next_index..total_index] {
+                *symbol = encoder.control(0);
+            }
+            symbols[total_index] = encoder.encode(frame[pixel * 4 + channel]);
+            next_index = total_index + 1;
+        }
+        for symbol in &mut symbols[next_index..] {
+            *symbol = encoder.control(0);
+        }
+
+        Self { symbols }
+    }
+
+    // The serialized bits as line levels of -1 or 1.
+    pub fn levels(&self) -> impl Iterator<Item = f32> + '_ {
+        self.symbols.iter().flat_map(|&symbol| {
+            (0..BITS_PER_SYMBOL).map(move |bit| if symbol >> bit & 1 == 1 { 1.0 } else { -1.0 })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::uniform;
+
+    #[test]
+    fn encodes_known_symbols() {
+        // Worked through by hand from the DVI 1.0 spec's encoder flowchart.
+        let cases = [
+            (0x00, 0, 0b01_0000_0000, -8),
+            (0x00, 2, 0b01_0000_0000, -6),
+            (0x00, -2, 0b11_1111_1111, 8),
+            (0xff, 0, 0b10_0000_0000, -8),
+            (0xff, 2, 0b10_0000_0000, -6),
+            (0xff, -2, 0b00_1111_1111, 4),
+        ];
+        for (byte, disparity, symbol, after) in cases {
+            assert_eq!(
+                encode_with_disparity(byte, disparity),
+                (symbol, after),
+                "{byte:#04x} at disparity {disparity}"
+            );
+        }
+    }
+
+    #[test]
+    fn disparity_stays_bounded_over_a_random_row() {
+        let mut encoder = TmdsEncoder::new();
+        let mut balance = 0;
+        for index in 0..H_DISPLAY as u64 * 100 {
+            let byte = ((uniform(45, index) + 1.0) * 128.0).min(255.0) as u8;
+            let symbol = encoder.encode(byte);
+
+            // The running disparity is the balance of ones and zeros actually sent.
+            balance += 2 * symbol.count_ones() as i32 - 10;
+            let disparity = encoder.disparity();
+            assert_eq!(disparity, balance);
+            assert!(disparity.abs() <= 10, "disparity reached {disparity}");
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use super::{encode_with_disparity, BITS_PER_SYMBOL, BIT_RATE};
+
+// Picks pixel bytes for HDMI by what their TMDS symbols radiate at one frequency, rather than
+// by gray level.
+//
+// Each symbol's ten bits, seen by a receiver tuned to `frequency`, add up to a phasor. Along
+// a row, the pixels' phasors add up with the same rotation from pixel to pixel that the
+// rendered samples go through, so choosing symbols whose phasors are proportional to the
+// samples reproduces the signal at `frequency` in the bit stream. The symbol a byte turns into
+// depends on the running disparity, so there's a table for each of its three cases.
+#[derive(Debug)]
+pub struct TmdsQuantizer {
+    // Indexed by disparity class: negative, zero or positive.
+    choices: [Vec<Choice>; 3],
+    // What full scale samples are mapped to. Some classes can only reach far in one direction,
+    // but a symbol that goes far pushes the disparity into the class that can go far the other
+    // way, so this is half the range of the narrowest class rather than its reach both ways.
+    scale: f32,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Choice {
+    // The symbol's phasor, projected onto the direction the symbols spread out the most in.
+    value: f32,
+    byte: u8,
+    disparity_change: i32,
+}
+
+// Representative disparities for the three classes. Only the sign affects the encoding.
+const CLASS_DISPARITIES: [i32; 3] = [-2, 0, 2];
+
+impl TmdsQuantizer {
+    pub fn new(frequency: u32) -> Self {
+        let omega = std::f64::consts::TAU * frequency as f64 / BIT_RATE;
+        let phasor = |symbol: u16| {
+            (0..BITS_PER_SYMBOL).fold((0.0, 0.0), |(re, im), bit| {
+                let level = if symbol >> bit & 1 == 1 { 1.0 } else { -1.0 };
+                let angle = omega * bit as f64;
+                (re + level * angle.cos(), im - level * angle.sin())
+            })
+        };
+
+        // (phasor, byte, disparity change) for every byte in every class.
+        let encoded = CLASS_DISPARITIES.map(|disparity| {
+            (0..=255)
+                .map(|byte| {
+                    let (symbol, next) = encode_with_disparity(byte, disparity);
+                    (phasor(symbol), byte, next - disparity)
+                })
+                .collect::<Vec<_>>()
+        });
+
+        // The principal axis of all the phasors, which gives the widest range of values.
+        let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+        for &((re, im), _, _) in encoded.iter().flatten() {
+            xx += re * re;
+            yy += im * im;
+            xy += re * im;
+        }
+        let axis = 0.5 * (2.0 * xy).atan2(xx - yy);
+        let (sin, cos) = axis.sin_cos();
+
+        let choices = encoded.each_ref().map(|class| {
+            let mut choices: Vec<Choice> = class
+                .iter()
+                .map(|&((re, im), byte, disparity_change)| Choice {
+                    value: (re * cos + im * sin) as f32,
+                    byte,
+                    disparity_change,
+                })
+                .collect();
+            choices.sort_by(|a, b| a.value.total_cmp(&b.value));
+            choices
+        });
+        let scale = choices
+            .iter()
+            .map(|class| (class[class.len() - 1].value - class[0].value) / 2.0)
+            .fold(f32::INFINITY, f32::min);
+
+        Self { choices, scale }
+    }
+
+    // Quantizes one row. The encoder's disparity starts from 0 on every row, since the
+    // blanking before it is a control period.
+    pub(crate) fn quantize(&self, samples: &[f32], levels: &mut [u8]) {
+        let mut disparity: i32 = 0;
+        for (level, &sample) in levels.iter_mut().zip(samples) {
+            let wanted = sample.clamp(-1.0, 1.0) * self.scale;
+
+            let class = &self.choices[(disparity.signum() + 1) as usize];
+            let above = class.partition_point(|choice| choice.value < wanted);
+            let choice = match (class.get(above.wrapping_sub(1)), class.get(above)) {
+                (Some(below), Some(above)) if wanted - below.value < above.value - wanted => below,
+                (_, Some(above)) => above,
+                (below, None) => below.unwrap(),
+            };
+
+            *level = choice.byte;
+            disparity += choice.disparity_change;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::uniform;
+    use crate::render::Quantizer;
+    use crate::tmds::TmdsEncoder;
+    use crate::{DOT_CLOCK, H_DISPLAY};
+    use std::sync::Arc;
+
+    #[test]
+    fn picks_the_nearest_symbol_for_the_disparity_sent() {
+        let quantizer = TmdsQuantizer::new(44_000_000);
+        let samples: Vec<f32> = (0..H_DISPLAY as u64).map(|n| uniform(8, n)).collect();
+        let mut levels = vec![0; samples.len()];
+        quantizer.quantize(&samples, &mut levels);
+
+        // Replays the row through a real encoder, which starts from 0 as well.
+        let mut encoder = TmdsEncoder::new();
+        for (&level, &sample) in levels.iter().zip(&samples) {
+            let disparity = encoder.disparity();
+            let class = &quantizer.choices[(disparity.signum() + 1) as usize];
+            encoder.encode(level);
+
+            let wanted = sample * quantizer.scale;
+            let chosen = class.iter().find(|choice| choice.byte == level).unwrap();
+            assert_eq!(chosen.disparity_change, encoder.disparity() - disparity);
+            let error = (chosen.value - wanted).abs();
+            assert!(class
+                .iter()
+                .all(|choice| (choice.value - wanted).abs() >= error));
+        }
+    }
+
+    #[test]
+    fn emits_more_at_the_carrier_than_gray_levels() {
+        for frequency in [10_000_000, 44_000_000, 100_000_000, 300_000_000] {
+            let omega = std::f64::consts::TAU * frequency as f64 / DOT_CLOCK as f64;
+            let samples: Vec<f32> = (0..H_DISPLAY)
+                .map(|n| (omega * n as f64).cos() as f32)
+                .collect();
+            // Power in the row's bit stream at `frequency`.
+            let power = |quantizer: Quantizer| {
+                let mut levels = vec![0; samples.len()];
+                quantizer.quantize(&samples, 0, 0, 0, &mut levels);
+                let mut encoder = TmdsEncoder::new();
+                let bit_omega = std::f64::consts::TAU * frequency as f64 / BIT_RATE;
+                let (mut re, mut im) = (0.0, 0.0);
+                for (n, &level) in levels.iter().enumerate() {
+                    let symbol = encoder.encode(level);
+                    for bit in 0..BITS_PER_SYMBOL {
+                        let value = if symbol >> bit & 1 == 1 { 1.0 } else { -1.0 };
+                        let angle = bit_omega * (n as f64 * BITS_PER_SYMBOL as f64 + bit as f64);
+                        re += value * angle.cos();
+                        im -= value * angle.sin();
+                    }
+                }
+                re * re + im * im
+            };
+
+            let tmds = power(Quantizer::Tmds(Arc::new(TmdsQuantizer::new(frequency))));
+            let gray = power(Quantizer::Round);
+            let gain = 10.0 * (tmds / gray).log10();
+            assert!(gain > 10.0, "{gain:.1} dB more at {frequency} Hz");
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use super::{TmdsStream, BIT_RATE};
+
+impl TmdsStream {
+    // Power of the bit stream at `frequency` in Hz, relative to a full-swing square wave at
+    // that frequency. Uses the Goertzel algorithm over the whole frame, so the frequency
+    // resolution is the frame rate.
+    pub fn power_at(&self, frequency: f64) -> f64 {
+        let omega = std::f64::consts::TAU * frequency / BIT_RATE;
+        let coefficient = 2.0 * omega.cos();
+
+        let mut count = 0;
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for level in self.levels() {
+            let s = level as f64 + coefficient * s1 - s2;
+            s2 = s1;
+            s1 = s;
+            count += 1;
+        }
+        let power = s1 * s1 + s2 * s2 - coefficient * s1 * s2;
+
+        // A square wave's fundamental has an amplitude of 4/π.
+        let full_scale = (count as f64 * 2.0 / std::f64::consts::PI).powi(2);
+        power / full_scale
+    }
+
+    // Magnitude spectrum in dB of `2^log_len` bits starting at bit `start`, Hann windowed.
+    // Bin k is at k × BIT_RATE / 2^log_len Hz, and only the bins up to half the bit rate
+    // are returned.
+    pub fn spectrum(&self, start: usize, log_len: u32) -> Vec<f32> {
+        let len = 1 << log_len;
+        let window = |i: usize| 0.5 - 0.5 * (std::f64::consts::TAU * i as f64 / len as f64).cos();
+        let mut re: Vec<f64> = self
+            .levels()
+            .skip(start)
+            .take(len)
+            .enumerate()
+            .map(|(i, level)| level as f64 * window(i))
+            .collect();
+        re.resize(len, 0.0);
+        let mut im = vec![0.0; len];
+
+        fft(&mut re, &mut im);
+
+        // Hann's coherent gain is 0.5.
+        let scale = 2.0 / (0.5 * len as f64);
+        (0..len / 2)
+            .map(|k| {
+                let magnitude = (re[k] * re[k] + im[k] * im[k]).sqrt() * scale;
+                (20.0 * magnitude.max(1e-12).log10()) as f32
+            })
+            .collect()
+    }
+}
+
+// In-place iterative radix-2 FFT.
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let len = re.len();
+    // Nothing to do, and the bit reversal below would shift by the whole width.
+    if len < 2 {
+        return;
+    }
+    let bits = len.trailing_zeros();
+    for i in 0..len {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut size = 2;
+    while size <= len {
+        let angle = -std::f64::consts::TAU / size as f64;
+        for start in (0..len).step_by(size) {
+            for k in 0..size / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + size / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        size *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fft_of_a_single_point_is_itself() {
+        let (mut re, mut im) = ([3.0], [-1.0]);
+        fft(&mut re, &mut im);
+        assert_eq!((re, im), ([3.0], [-1.0]));
+
+        fft(&mut [], &mut []);
+    }
+
+    #[test]
+    fn fft_finds_a_cosine() {
+        let len = 64;
+        let mut re: Vec<f64> = (0..len)
+            .map(|i| (std::f64::consts::TAU * 5.0 * i as f64 / len as f64).cos())
+            .collect();
+        let mut im = vec![0.0; len];
+        fft(&mut re, &mut im);
+
+        for k in 0..len {
+            let expected = if k == 5 || k == len - 5 {
+                len as f64 / 2.0
+            } else {
+                0.0
+            };
+            let magnitude = (re[k] * re[k] + im[k] * im[k]).sqrt();
+            assert!(
+                (magnitude - expected).abs() < 1e-9,
+                "bin {k} is {magnitude}"
+            );
+        }
+    }
+}